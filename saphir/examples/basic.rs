@@ -247,5 +247,6 @@ async fn main() -> Result<(), SaphirError> {
         .configure_middlewares(|m| m.apply(log_middleware, vec!["/**/*.html"], None).apply(StatsData::new(), vec!["/"], None))
         .build();
 
-    server.run().await
+    server.run().await?;
+    Ok(())
 }
@@ -26,7 +26,7 @@ struct User {
 
 struct UserController {}
 
-#[controller(name = "users", version = 1, prefix = "api")]
+#[controller(name = "users", version = 1, prefix = "api", timeout = 10000, body_limit = 1MB)]
 impl UserController {
     #[get("/<user_id>")]
     async fn get_user(&self, user_id: String, action: Option<u16>) -> (u16, String) {
@@ -34,6 +34,7 @@ impl UserController {
     }
 
     #[post("/json")]
+    #[body_limit("16KB")]
     async fn post_user_json(&self, user: Json<User>) -> (u16, Json<User>) {
         (200, user)
     }
@@ -54,11 +55,14 @@ impl UserController {
 
     #[get("/")]
     #[guard(PrintGuard, init_expr = "UserController::BASE_PATH")]
+    #[timeout(2000)]
     async fn list_user(&self, _req: Request<Body>) -> (u16, String) {
         (200, "Yo".to_string())
     }
 
     #[post("/multi")]
+    #[timeout(300000)]
+    #[body_limit(100MB)]
     async fn multipart(&self, mul: Multipart) -> (u16, String) {
         let mut multipart_image_count = 0;
         while let Ok(Some(mut f)) = mul.next_field().await {
@@ -120,5 +124,6 @@ async fn main() -> Result<(), SaphirError> {
         .configure_router(|r| r.controller(UserController {}))
         .build();
 
-    server.run().await
+    server.run().await?;
+    Ok(())
 }
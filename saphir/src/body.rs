@@ -19,50 +19,53 @@ pub use json::Json;
 use std::ops::DerefMut;
 use tokio::stream::StreamExt;
 
-#[doc(hidden)]
-pub(crate) static mut REQUEST_BODY_BYTES_LIMIT: Option<usize> = None;
-
 pub(crate) enum BodyInner {
-    Raw(RawBody),
+    /// Body still to be received, with the maximum number of bytes it can hold
+    /// and the number of bytes streamed so far
+    Raw(RawBody, Option<usize>, usize),
     Memory(Bytes),
 }
 
 impl BodyInner {
     pub fn empty() -> Self {
-        BodyInner::Raw(RawBody::empty())
+        BodyInner::Raw(RawBody::empty(), None, 0)
     }
 
     #[inline]
     pub(crate) fn from_raw(raw: RawBody) -> Self {
-        BodyInner::Raw(raw)
+        BodyInner::Raw(raw, None, 0)
     }
 
+    /// Convert into a hyper body, still enforcing the limit of the body if
+    /// any
     #[inline]
     pub(crate) fn into_raw(self) -> RawBody {
         match self {
-            BodyInner::Raw(r) => r,
+            BodyInner::Raw(r, None, _) => r,
+            BodyInner::Raw(r, Some(limit), read) => {
+                let mut limited = BodyInner::Raw(r, Some(limit), read);
+                RawBody::wrap_stream(futures::stream::poll_fn(move |cx| Pin::new(&mut limited).poll_data(cx)))
+            }
             BodyInner::Memory(b) => RawBody::from(b),
         }
     }
 
     pub async fn load(self) -> Result<Bytes, SaphirError> {
-        unsafe {
-            if let Some(0) = REQUEST_BODY_BYTES_LIMIT {
-                return Ok(Bytes::new());
-            }
-        }
         match self {
-            BodyInner::Raw(mut r) => {
+            BodyInner::Raw(mut r, limit, read) => {
+                let exceeds = |len: usize| limit.filter(|limit| read + len > *limit);
+                if let Some(limit) = exceeds(r.size_hint().lower() as usize) {
+                    return Err(SaphirError::PayloadTooLarge(limit));
+                }
+
                 let first = if let Some(buf) = r.next().await.transpose().map_err(SaphirError::from)? {
                     buf
                 } else {
                     return Ok(Bytes::new());
                 };
 
-                unsafe {
-                    if REQUEST_BODY_BYTES_LIMIT.as_ref().filter(|p| first.len() >= **p).is_some() {
-                        return Ok(first);
-                    }
+                if let Some(limit) = exceeds(first.len()) {
+                    return Err(SaphirError::PayloadTooLarge(limit));
                 }
 
                 let second = if let Some(buf) = r.next().await.transpose().map_err(SaphirError::from)? {
@@ -76,18 +79,14 @@ impl BodyInner {
                 vec.extend_from_slice(first.as_ref());
                 vec.extend_from_slice(second.as_ref());
 
-                unsafe {
-                    if REQUEST_BODY_BYTES_LIMIT.as_ref().filter(|p| vec.len() >= **p).is_some() {
-                        return Ok(vec.into());
-                    }
+                if let Some(limit) = exceeds(vec.len()) {
+                    return Err(SaphirError::PayloadTooLarge(limit));
                 }
 
                 while let Some(buf) = r.next().await.transpose().map_err(SaphirError::from)? {
                     vec.extend_from_slice(buf.as_ref());
-                    unsafe {
-                        if REQUEST_BODY_BYTES_LIMIT.as_ref().filter(|p| vec.len() >= **p).is_some() {
-                            break;
-                        }
+                    if let Some(limit) = exceeds(vec.len()) {
+                        return Err(SaphirError::PayloadTooLarge(limit));
                     }
                 }
 
@@ -103,7 +102,7 @@ where
     T: Into<RawBody>,
 {
     fn from(b: T) -> Self {
-        BodyInner::Raw(b.into())
+        BodyInner::Raw(b.into(), None, 0)
     }
 }
 
@@ -146,6 +145,16 @@ where
         self.inner.unwrap_or_else(BodyInner::empty).into_raw()
     }
 
+    /// Set the maximum number of bytes of the body, loading or streaming a
+    /// larger body fails with `SaphirError::PayloadTooLarge`. The limit has no
+    /// effect once the body is loaded.
+    #[inline]
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        if let Some(BodyInner::Raw(_, l, _)) = self.inner.as_mut() {
+            *l = limit;
+        }
+    }
+
     /// Performing `take` will give your a owned version of the body, leaving a
     /// empty one behind
     #[inline]
@@ -463,20 +472,21 @@ impl HttpBody for BodyInner {
             } else {
                 Poll::Ready(None)
             }
-        } else {
-            let p = unsafe {
-                self.map_unchecked_mut(|s| match s {
-                    BodyInner::Raw(r) => r,
-                    BodyInner::Memory(_) => unreachable!("This is unreachable since checked above"),
-                })
-                .poll_data(cx)
-            };
-
-            match p {
-                Poll::Ready(Some(res)) => Poll::Ready(Some(res.map_err(SaphirError::from))),
+        } else if let BodyInner::Raw(r, limit, read) = self.deref_mut() {
+            match Pin::new(r).poll_data(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    *read += chunk.len();
+                    match limit {
+                        Some(limit) if *read > *limit => Poll::Ready(Some(Err(SaphirError::PayloadTooLarge(*limit)))),
+                        _ => Poll::Ready(Some(Ok(chunk))),
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(SaphirError::from(e)))),
                 Poll::Ready(None) => Poll::Ready(None),
                 Poll::Pending => Poll::Pending,
             }
+        } else {
+            unreachable!("This is unreachable since checked above")
         }
     }
 
@@ -486,7 +496,7 @@ impl HttpBody for BodyInner {
         } else {
             let p = unsafe {
                 self.map_unchecked_mut(|s| match s {
-                    BodyInner::Raw(r) => r,
+                    BodyInner::Raw(r, ..) => r,
                     BodyInner::Memory(_) => unreachable!("This is unreachable since checked above"),
                 })
                 .poll_trailers(cx)
@@ -501,14 +511,14 @@ impl HttpBody for BodyInner {
 
     fn is_end_stream(&self) -> bool {
         match self {
-            BodyInner::Raw(r) => r.is_end_stream(),
+            BodyInner::Raw(r, ..) => r.is_end_stream(),
             BodyInner::Memory(b) => b.remaining() > 0,
         }
     }
 
     fn size_hint(&self) -> SizeHint {
         match self {
-            BodyInner::Raw(r) => r.size_hint(),
+            BodyInner::Raw(r, ..) => r.size_hint(),
             BodyInner::Memory(b) => SizeHint::with_exact(b.remaining() as u64),
         }
     }
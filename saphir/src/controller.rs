@@ -2,9 +2,9 @@
 //! the client.
 //!
 //! More specifically a Controller defines a list of endpoint (Handlers) that
-//! handle a request and return a Future of a [`Responder`](crate::responder::Responder).
-//! The Responder is responsible for the [`Response`](crate::response::Response)
-//! being generated.
+//! handle a request and return a Future of a
+//! [`Responder`](crate::responder::Responder). The Responder is responsible for
+//! the [`Response`](crate::response::Response) being generated.
 //!
 //! To create a controller, simply implement the
 //! [Controller](trait.Controller.html) trait on a struct:
@@ -35,6 +35,7 @@ use crate::{
     guard::{Builder as GuardBuilder, GuardChain, GuardChainEnd},
     request::Request,
     responder::{DynResponder, Responder},
+    router::RouteOptions,
 };
 use futures::future::BoxFuture;
 use futures_util::future::{Future, FutureExt};
@@ -47,6 +48,7 @@ pub type ControllerEndpoint<C> = (
     &'static str,
     Box<dyn DynControllerHandler<C, Body> + Send + Sync>,
     Box<dyn GuardChain>,
+    RouteOptions,
 );
 
 /// Trait that defines how a controller handles its requests
@@ -85,8 +87,8 @@ pub trait DynControllerHandler<C, B> {
     fn dyn_handle(&self, controller: &'static C, req: Request<B>) -> BoxFuture<'static, Box<dyn DynResponder + Send>>;
 }
 
-/// Builder to simplify returning a list of endpoints in the `handlers` method of
-/// the controller trait
+/// Builder to simplify returning a list of endpoints in the `handlers` method
+/// of the controller trait
 #[derive(Default)]
 pub struct EndpointsBuilder<C: Controller> {
     handlers: Vec<ControllerEndpoint<C>>,
@@ -129,7 +131,8 @@ impl<C: Controller> EndpointsBuilder<C> {
     where
         H: 'static + DynControllerHandler<C, Body> + Send + Sync,
     {
-        self.handlers.push((None, method, route, Box::new(handler), GuardBuilder::default().build()));
+        self.handlers
+            .push((None, method, route, Box::new(handler), GuardBuilder::default().build(), RouteOptions::new()));
         self
     }
 
@@ -141,8 +144,14 @@ impl<C: Controller> EndpointsBuilder<C> {
         F: FnOnce(GuardBuilder<GuardChainEnd>) -> GuardBuilder<Chain>,
         Chain: GuardChain + 'static,
     {
-        self.handlers
-            .push((None, method, route, Box::new(handler), guards(GuardBuilder::default()).build()));
+        self.handlers.push((
+            None,
+            method,
+            route,
+            Box::new(handler),
+            guards(GuardBuilder::default()).build(),
+            RouteOptions::new(),
+        ));
         self
     }
 
@@ -152,8 +161,14 @@ impl<C: Controller> EndpointsBuilder<C> {
     where
         H: 'static + DynControllerHandler<C, Body> + Send + Sync,
     {
-        self.handlers
-            .push((Some(handler_name), method, route, Box::new(handler), GuardBuilder::default().build()));
+        self.handlers.push((
+            Some(handler_name),
+            method,
+            route,
+            Box::new(handler),
+            GuardBuilder::default().build(),
+            RouteOptions::new(),
+        ));
         self
     }
 
@@ -165,8 +180,42 @@ impl<C: Controller> EndpointsBuilder<C> {
         F: FnOnce(GuardBuilder<GuardChainEnd>) -> GuardBuilder<Chain>,
         Chain: GuardChain + 'static,
     {
-        self.handlers
-            .push((Some(handler_name), method, route, Box::new(handler), guards(GuardBuilder::default()).build()));
+        self.handlers.push((
+            Some(handler_name),
+            method,
+            route,
+            Box::new(handler),
+            guards(GuardBuilder::default()).build(),
+            RouteOptions::new(),
+        ));
+        self
+    }
+
+    /// Set the options of the endpoint added last, overriding the defaults
+    /// of the listener
+    ///
+    /// ```rust
+    /// # use saphir::prelude::*;
+    /// # struct ReportController;
+    /// # impl Controller for ReportController {
+    /// #     const BASE_PATH: &'static str = "/reports";
+    /// #     fn handlers(&self) -> Vec<ControllerEndpoint<Self>> where Self: Sized { EndpointsBuilder::new().build() }
+    /// # }
+    /// impl ReportController {
+    ///     async fn generate(&self, req: Request<Body>) -> impl Responder {200}
+    /// }
+    ///
+    /// let b: EndpointsBuilder<ReportController> = EndpointsBuilder::new()
+    ///     .add(Method::POST, "/", ReportController::generate)
+    ///     .options(RouteOptions::new().timeout(120_000));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if no endpoint was added yet
+    #[inline]
+    pub fn options(mut self, options: RouteOptions) -> Self {
+        self.handlers.last_mut().expect("Options must follow the endpoint they apply to").5 = options;
         self
     }
 
@@ -58,8 +58,8 @@ pub enum SaphirError {
     InvalidParameter(String, bool),
     ///
     RequestTimeout,
-    /// Attempted to build stack twice
-    StackAlreadyInitialized,
+    /// The request body exceeds the limit in bytes of its handler
+    PayloadTooLarge(usize),
 }
 
 impl Debug for SaphirError {
@@ -82,7 +82,7 @@ impl Debug for SaphirError {
             SaphirError::MissingParameter(d, _) => std::fmt::Debug::fmt(d, f),
             SaphirError::InvalidParameter(d, _) => std::fmt::Debug::fmt(d, f),
             SaphirError::RequestTimeout => f.write_str("RequestTimeout"),
-            SaphirError::StackAlreadyInitialized => f.write_str("StackAlreadyInitialized"),
+            SaphirError::PayloadTooLarge(limit) => f.debug_tuple("PayloadTooLarge").field(limit).finish(),
         }
     }
 }
@@ -111,7 +111,7 @@ impl SaphirError {
             SaphirError::ResponseMoved => builder.status(500),
             SaphirError::Responder(mut r) => r.dyn_respond(builder, ctx),
             SaphirError::RequestTimeout => builder.status(408),
-            SaphirError::StackAlreadyInitialized => builder.status(500),
+            SaphirError::PayloadTooLarge(_) => builder.status(413),
         }
     }
 
@@ -183,10 +183,10 @@ impl SaphirError {
             SaphirError::RequestTimeout => {
                 warn!("{}Request timed out", op_id);
             }
-            SaphirError::Responder(_) => {}
-            SaphirError::StackAlreadyInitialized => {
-                warn!("{}Attempted to initialize stack twice", op_id);
+            SaphirError::PayloadTooLarge(limit) => {
+                warn!("{}Request body exceeds the limit of {} bytes", op_id, limit);
             }
+            SaphirError::Responder(_) => {}
         }
     }
 }
@@ -18,6 +18,9 @@ use std::{
 };
 use tokio::sync::RwLock;
 
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+
 #[derive(Default)]
 struct CacheInner {
     pub cache: HashMap<(String, Compression), Vec<u8>>,
@@ -29,6 +32,8 @@ pub struct FileCache {
     inner: Arc<RwLock<CacheInner>>,
     max_file_size: u64,
     max_capacity: u64,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
 }
 
 impl FileCache {
@@ -37,6 +42,26 @@ impl FileCache {
             inner: Arc::new(RwLock::new(Default::default())),
             max_file_size,
             max_capacity,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Record the hits, misses and size of the cache in `metrics`
+    #[cfg(feature = "metrics")]
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = Some(metrics);
+    }
+
+    #[allow(unused_variables)]
+    fn record_lookup(&self, hit: bool) {
+        #[cfg(feature = "metrics")]
+        {
+            match &self.metrics {
+                Some(metrics) if hit => metrics.file_cache_hit(),
+                Some(metrics) => metrics.file_cache_miss(),
+                None => {}
+            }
         }
     }
 
@@ -60,6 +85,12 @@ impl FileCache {
     pub async fn insert(&mut self, key: (String, Compression), value: Vec<u8>) {
         let mut inner = self.inner.write().await;
         inner.size += value.len() as u64;
+        #[cfg(feature = "metrics")]
+        {
+            if let Some(metrics) = &self.metrics {
+                metrics.file_cache_insert(value.len() as u64);
+            }
+        }
         inner.cache.insert(key, value);
     }
 
@@ -70,8 +101,10 @@ impl FileCache {
     pub async fn open_file(&mut self, path: &PathBuf, compression: Compression) -> Result<FileStream, SaphirError> {
         let path_str = path.to_str().unwrap_or_default();
         if let Some(cached_file) = self.get((path_str.to_string(), compression)).await {
+            self.record_lookup(true);
             Ok(FileStream::new(cached_file))
         } else if let Some(cached_raw_file) = self.get((path_str.to_string(), Compression::Raw)).await {
+            self.record_lookup(true);
             let file_size = cached_raw_file.get_path().size();
             if file_size + self.get_size().await <= self.max_capacity && file_size <= self.max_file_size {
                 let mime = cached_raw_file.get_mime().cloned();
@@ -87,6 +120,7 @@ impl FileCache {
                 Ok(FileStream::new(FileCursor::new(compressed_file, mime, path.clone())))
             }
         } else {
+            self.record_lookup(false);
             let file = File::open(path_str).await?;
             let file_size = file.get_size();
             if file_size + self.get_size().await <= self.max_capacity && file_size <= self.max_file_size {
@@ -108,10 +142,12 @@ impl FileCache {
     pub async fn open_file_with_range(&mut self, path: &PathBuf, range: (u64, u64)) -> Result<FileStream, SaphirError> {
         let path_str = path.to_str().unwrap_or_default();
         if let Some(cached_file) = self.get((path_str.to_string(), Compression::Raw)).await {
+            self.record_lookup(true);
             let mut file_stream = FileStream::new(cached_file);
             file_stream.set_range(range).await?;
             Ok(file_stream)
         } else {
+            self.record_lookup(false);
             let mut file_stream = FileStream::new(File::open(path_str).await?);
             file_stream.set_range(range).await?;
             Ok(file_stream)
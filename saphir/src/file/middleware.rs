@@ -1,3 +1,5 @@
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
 use crate::{
     file::{
         cache::FileCache,
@@ -217,6 +219,8 @@ pub struct FileMiddlewareBuilder {
     max_capacity: Option<u64>,
     file_not_found_handler: Option<Box<dyn 'static + DynHandler<Body> + Send + Sync>>,
     max_age: i64,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
 }
 
 impl FileMiddlewareBuilder {
@@ -230,6 +234,8 @@ impl FileMiddlewareBuilder {
             max_capacity: None,
             file_not_found_handler: None,
             max_age: DEFAULT_MAX_AGE,
+            #[cfg(feature = "metrics")]
+            metrics: None,
         }
     }
 
@@ -301,16 +307,32 @@ impl FileMiddlewareBuilder {
         self
     }
 
+    /// Record the hits, misses and size of the in-memory cache in `metrics`.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: &Metrics) -> Self {
+        self.metrics = Some(metrics.clone());
+        self
+    }
+
     pub fn build(self) -> Result<FileMiddleware, SaphirError> {
+        #[allow(unused_mut)]
+        let mut cache = FileCache::new(
+            self.max_file_size.unwrap_or(DEFAULT_CACHE_MAX_FILE_SIZE),
+            self.max_capacity.unwrap_or(DEFAULT_CACHE_MAX_CAPACITY),
+        );
+        #[cfg(feature = "metrics")]
+        {
+            if let Some(metrics) = self.metrics {
+                cache.set_metrics(metrics);
+            }
+        }
+
         Ok(FileMiddleware {
             base_path: self.base_path,
             www_path: self.www_path,
             index_files: self.index_files.unwrap_or_else(|| DEFAULT_INDEX_FILES.iter().map(|s| s.to_string()).collect()),
             try_files: self.try_files.unwrap_or_else(|| DEFAULT_TRY_FILES.iter().map(|s| s.to_string()).collect()),
-            cache: FileCache::new(
-                self.max_file_size.unwrap_or(DEFAULT_CACHE_MAX_FILE_SIZE),
-                self.max_capacity.unwrap_or(DEFAULT_CACHE_MAX_CAPACITY),
-            ),
+            cache,
             file_not_found_handler: self.file_not_found_handler,
             max_age: self.max_age,
         })
@@ -0,0 +1,411 @@
+//! Resolution of the original client of a request received through reverse
+//! proxies, from the RFC 7239 `Forwarded` header or the `X-Forwarded-For`,
+//! `X-Forwarded-Proto` and `X-Forwarded-Host` headers.
+//!
+//! Those headers are only trusted when they were added by a proxy listed in
+//! the trusted proxies of the listener, see
+//! [`ListenerBuilder::trusted_proxies`](crate::server::ListenerBuilder::trusted_proxies).
+use crate::{error::SaphirError, request::PeerAddr};
+use http::{header::HOST, HeaderMap, Uri};
+use std::{
+    fmt::{Display, Formatter},
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+
+/// A range of ip addresses in CIDR notation, e.g. `10.0.0.0/8` or
+/// `2001:db8::/32`. A single address is parsed as a range containing only it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct IpCidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self, SaphirError> {
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max {
+            return Err(SaphirError::Other(format!("Invalid prefix length {} for {}", prefix_len, addr)));
+        }
+
+        Ok(IpCidr { addr, prefix_len })
+    }
+
+    /// Whether `ip` is in this range, ipv4-mapped ipv6 addresses are matched
+    /// against ipv4 ranges
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => prefix_matches(&net.octets(), &ip.octets(), self.prefix_len),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => prefix_matches(&net.octets(), &ip.octets(), self.prefix_len),
+            (IpAddr::V4(net), IpAddr::V6(ip)) => ip
+                .to_ipv4()
+                .filter(|_| ip.segments()[..6] == [0, 0, 0, 0, 0, 0xFFFF])
+                .map(|ip| prefix_matches(&net.octets(), &ip.octets(), self.prefix_len))
+                .unwrap_or(false),
+            (IpAddr::V6(_), IpAddr::V4(_)) => false,
+        }
+    }
+}
+
+fn prefix_matches(net: &[u8], ip: &[u8], prefix_len: u8) -> bool {
+    let full_bytes = (prefix_len / 8) as usize;
+    let remaining_bits = prefix_len % 8;
+    if net[..full_bytes] != ip[..full_bytes] {
+        return false;
+    }
+
+    if remaining_bits == 0 {
+        return true;
+    }
+
+    let mask = 0xFFu8 << (8 - remaining_bits);
+    net[full_bytes] & mask == ip[full_bytes] & mask
+}
+
+impl FromStr for IpCidr {
+    type Err = SaphirError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SaphirError::Other(format!("Invalid CIDR: {}", s));
+        match s.find('/') {
+            Some(slash) => IpCidr::new(s[..slash].parse().map_err(|_| invalid())?, s[slash + 1..].parse().map_err(|_| invalid())?),
+            None => {
+                let addr: IpAddr = s.parse().map_err(|_| invalid())?;
+                IpCidr::new(addr, if addr.is_ipv4() { 32 } else { 128 })
+            }
+        }
+    }
+}
+
+impl From<IpAddr> for IpCidr {
+    fn from(addr: IpAddr) -> Self {
+        IpCidr {
+            addr,
+            prefix_len: if addr.is_ipv4() { 32 } else { 128 },
+        }
+    }
+}
+
+impl Display for IpCidr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Proxies trusted to report the client of the requests they forward
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    ranges: Vec<IpCidr>,
+    unix_peers: bool,
+}
+
+impl TrustedProxies {
+    pub fn new<I: IntoIterator<Item = IpCidr>>(ranges: I) -> Self {
+        TrustedProxies {
+            ranges: ranges.into_iter().collect(),
+            unix_peers: false,
+        }
+    }
+
+    /// Also trust the peers of unix domain sockets, which are local
+    /// processes. Off by default.
+    #[inline]
+    pub fn trust_unix_peers(mut self, trusted: bool) -> Self {
+        self.unix_peers = trusted;
+        self
+    }
+
+    /// Whether the peers of unix domain sockets are trusted
+    #[inline]
+    pub fn trusts_unix_peers(&self) -> bool {
+        self.unix_peers
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+
+    fn trusts(&self, peer: Option<&PeerAddr>) -> bool {
+        match peer {
+            Some(PeerAddr::Tcp(addr)) => self.contains(&addr.ip()),
+            #[cfg(unix)]
+            Some(PeerAddr::Unix(_)) => self.unix_peers,
+            None => false,
+        }
+    }
+}
+
+/// The original client of a request
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct ClientInfo<'a> {
+    pub addr: Option<IpAddr>,
+    pub scheme: &'a str,
+    pub host: Option<&'a str>,
+}
+
+/// A hop of the forwarding chain, as reported by the proxy that received it
+#[derive(Debug, Default)]
+struct Hop<'a> {
+    /// `None` when the client of the proxy is unknown or obfuscated
+    addr: Option<IpAddr>,
+    proto: Option<&'a str>,
+    host: Option<&'a str>,
+}
+
+/// Walk the forwarding headers from right to left, stopping at the first
+/// hop which is not a trusted proxy
+pub(crate) fn resolve<'a>(headers: &'a HeaderMap, uri: &'a Uri, peer: Option<&PeerAddr>, scheme: &'a str, trusted: Option<&TrustedProxies>) -> ClientInfo<'a> {
+    let mut client = ClientInfo {
+        addr: peer.and_then(|p| p.ip()),
+        scheme,
+        host: headers.get(HOST).and_then(|h| h.to_str().ok()).or_else(|| uri.authority().map(|a| a.as_str())),
+    };
+
+    let trusted = match trusted {
+        Some(trusted) if trusted.trusts(peer) => trusted,
+        _ => return client,
+    };
+
+    let hops = forwarded_hops(headers).or_else(|| x_forwarded_hops(headers)).unwrap_or_default();
+    for hop in hops.into_iter().rev() {
+        client.addr = hop.addr;
+        if let Some(proto) = hop.proto {
+            client.scheme = proto;
+        }
+        if let Some(host) = hop.host {
+            client.host = Some(host);
+        }
+
+        match hop.addr {
+            Some(ip) if trusted.contains(&ip) => {}
+            _ => break,
+        }
+    }
+
+    client
+}
+
+fn header_values<'a>(headers: &'a HeaderMap, name: &str) -> Vec<&'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(split_unquoted(','))
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+/// Split on `sep`, ignoring separators inside quoted strings
+fn split_unquoted(sep: char) -> impl Fn(&str) -> Vec<&str> {
+    move |s: &str| {
+        let mut parts = Vec::new();
+        let mut quoted = false;
+        let mut start = 0;
+        for (i, c) in s.char_indices() {
+            match c {
+                '"' => quoted = !quoted,
+                c if c == sep && !quoted => {
+                    parts.push(&s[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        parts.push(&s[start..]);
+        parts
+    }
+}
+
+fn unquote(s: &str) -> &str {
+    let s = s.trim();
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        &s[1..s.len() - 1]
+    } else {
+        s
+    }
+}
+
+/// Parse a node of a forwarding header: an ip, optionally with a port and
+/// between brackets for ipv6
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = unquote(node);
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| node.strip_prefix('[').and_then(|n| n.strip_suffix(']')).and_then(|n| n.parse().ok()))
+}
+
+fn forwarded_hops(headers: &HeaderMap) -> Option<Vec<Hop<'_>>> {
+    let elements = header_values(headers, http::header::FORWARDED.as_str());
+    if elements.is_empty() {
+        return None;
+    }
+
+    let hops = elements
+        .into_iter()
+        .map(|element| {
+            let mut hop = Hop::default();
+            for pair in split_unquoted(';')(element) {
+                let (name, value) = match pair.find('=') {
+                    Some(eq) => (&pair[..eq], &pair[eq + 1..]),
+                    None => continue,
+                };
+
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "for" => hop.addr = parse_node(value),
+                    "proto" => hop.proto = Some(unquote(value)),
+                    "host" => hop.host = Some(unquote(value)),
+                    _ => {}
+                }
+            }
+            hop
+        })
+        .collect();
+
+    Some(hops)
+}
+
+fn x_forwarded_hops(headers: &HeaderMap) -> Option<Vec<Hop<'_>>> {
+    let addrs = header_values(headers, X_FORWARDED_FOR);
+    if addrs.is_empty() {
+        return None;
+    }
+
+    // Proto & host lists are aligned on the right of the addresses, a proxy
+    // appending to one of them appends to the others
+    let protos = header_values(headers, X_FORWARDED_PROTO);
+    let hosts = header_values(headers, X_FORWARDED_HOST);
+
+    let hops = addrs
+        .iter()
+        .enumerate()
+        .map(|(i, addr)| Hop {
+            addr: parse_node(addr),
+            proto: aligned(&protos, addrs.len(), i),
+            host: aligned(&hosts, addrs.len(), i),
+        })
+        .collect();
+
+    Some(hops)
+}
+
+/// Value of `values` at the position `i` of a list of `len` elements, both
+/// lists being aligned on their right
+fn aligned<'a>(values: &[&'a str], len: usize, i: usize) -> Option<&'a str> {
+    (values.len() + i).checked_sub(len).and_then(|i| values.get(i).copied())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn trusted() -> TrustedProxies {
+        TrustedProxies::new(vec!["10.0.0.0/8".parse().unwrap(), "fd00::/8".parse().unwrap()])
+    }
+
+    fn resolve_with(headers: &[(&str, &str)], peer: &str) -> (Option<IpAddr>, String, Option<String>) {
+        let mut map = HeaderMap::new();
+        map.insert(HOST, HeaderValue::from_static("internal.local"));
+        for (name, value) in headers {
+            map.append(
+                http::header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        let uri = Uri::from_static("/");
+        let peer = PeerAddr::Tcp(peer.parse().unwrap());
+        let trusted = trusted();
+        let client = resolve(&map, &uri, Some(&peer), "http", Some(&trusted));
+        (client.addr, client.scheme.to_string(), client.host.map(str::to_string))
+    }
+
+    #[test]
+    fn cidr_contains() {
+        let v4: IpCidr = "192.168.1.0/20".parse().unwrap();
+        assert!(v4.contains(&"192.168.15.255".parse().unwrap()));
+        assert!(!v4.contains(&"192.168.16.0".parse().unwrap()));
+        assert!(v4.contains(&"::ffff:192.168.1.1".parse().unwrap()));
+        assert!(!v4.contains(&"::192.168.1.1".parse().unwrap()));
+
+        let single: IpCidr = "2001:db8::1".parse().unwrap();
+        assert!(single.contains(&"2001:db8::1".parse().unwrap()));
+        assert!(!single.contains(&"2001:db8::2".parse().unwrap()));
+
+        let all: IpCidr = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains(&"8.8.8.8".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<IpCidr>().is_err());
+        assert!("10.0.0/8".parse::<IpCidr>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_peers_are_trusted_explicitly() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_static("1.2.3.4"));
+        let uri = Uri::from_static("/");
+        let peer = PeerAddr::Unix(crate::request::UnixPeerAddr {
+            path: None,
+            uid: Some(0),
+            gid: Some(0),
+        });
+
+        let client = resolve(&headers, &uri, Some(&peer), "http", Some(&trusted()));
+        assert_eq!(client.addr, None);
+        let client = resolve(&headers, &uri, Some(&peer), "http", Some(&trusted().trust_unix_peers(true)));
+        assert_eq!(client.addr, Some("1.2.3.4".parse().unwrap()));
+    }
+
+    #[test]
+    fn untrusted_peer_is_the_client() {
+        let (addr, scheme, host) = resolve_with(&[("x-forwarded-for", "1.2.3.4"), ("x-forwarded-proto", "https")], "8.8.8.8:1234");
+        assert_eq!(addr, Some("8.8.8.8".parse().unwrap()));
+        assert_eq!(scheme, "http");
+        assert_eq!(host.as_deref(), Some("internal.local"));
+    }
+
+    #[test]
+    fn x_forwarded_headers() {
+        let (addr, scheme, host) = resolve_with(
+            &[
+                ("x-forwarded-for", "6.6.6.6, 1.2.3.4"),
+                ("x-forwarded-for", "10.0.0.2"),
+                ("x-forwarded-proto", "https"),
+                ("x-forwarded-host", "example.com"),
+            ],
+            "10.0.0.1:1234",
+        );
+        // 6.6.6.6 was set by the client itself, 1.2.3.4 is not trusted
+        assert_eq!(addr, Some("1.2.3.4".parse().unwrap()));
+        assert_eq!(scheme, "https");
+        assert_eq!(host.as_deref(), Some("example.com"));
+    }
+
+    #[test]
+    fn forwarded_header() {
+        let (addr, scheme, host) = resolve_with(
+            &[(
+                "forwarded",
+                r#"for=6.6.6.6, for="[2001:db8:cafe::17]:4711";proto=https;host="example.com:8443", For=10.1.1.1;proto=http"#,
+            )],
+            "10.0.0.1:1234",
+        );
+        assert_eq!(addr, Some("2001:db8:cafe::17".parse().unwrap()));
+        assert_eq!(scheme, "https");
+        assert_eq!(host.as_deref(), Some("example.com:8443"));
+
+        // Forwarded takes precedence, an unknown client stops the walk
+        let (addr, ..) = resolve_with(&[("forwarded", "for=unknown, for=10.0.0.3"), ("x-forwarded-for", "1.2.3.4")], "10.0.0.1:1234");
+        assert_eq!(addr, None);
+    }
+}
@@ -1,13 +1,20 @@
 //! A guard is called before the request is processed by the router and
 //! can modify the request data or stops request processing by returning a
 //! response immediately.
+//!
+//! Guards run before the request body is read, a guard rejecting a request
+//! sent with `Expect: 100-continue` answers it before the client streams its
+//! body.
 
 use crate::{
     body::Body,
     request::Request,
     responder::{DynResponder, Responder},
 };
-use futures::{future::BoxFuture, FutureExt};
+use futures::{
+    future::{ready, BoxFuture, Ready},
+    FutureExt,
+};
 use futures_util::future::Future;
 
 /// Auto trait implementation over every function that match the definition of a
@@ -34,6 +41,66 @@ where
     }
 }
 
+/// Guard deciding from the headers of a request sent with
+/// `Expect: 100-continue` whether the client may stream its body, or gets a
+/// final response right away. Other requests are left untouched.
+///
+/// When the check passes, the `100 Continue` is sent once the handler reads
+/// the body.
+///
+/// Since requests without `Expect: 100-continue` skip the check, this guard
+/// only saves the upload of bodies that would be refused anyway, it is not an
+/// access-control guard.
+///
+/// ```rust
+/// # use saphir::prelude::*;
+/// # use saphir::guard::ExpectContinue;
+/// # use saphir::router::Builder as RBuilder;
+/// # let builder = RBuilder::default();
+/// async fn upload(mut req: Request) -> Result<u16, SaphirError> {
+///     let _file = req.body_mut().take_as::<Bytes>().await?;
+///     Ok(201)
+/// }
+///
+/// builder.route_with_guards("/upload", Method::PUT, upload, |g| {
+///     g.apply(ExpectContinue::new(|req: &Request| match req.headers().get(header::CONTENT_TYPE) {
+///         Some(t) if t == "application/pdf" => Ok(()),
+///         _ => Err(415),
+///     }))
+/// });
+/// ```
+pub struct ExpectContinue<F> {
+    check: F,
+}
+
+impl<F, R> ExpectContinue<F>
+where
+    F: Fn(&Request<Body>) -> Result<(), R>,
+    R: Responder + Send,
+{
+    /// Create the guard from a check run on the request head
+    pub fn new(check: F) -> Self {
+        ExpectContinue { check }
+    }
+}
+
+impl<F, R> Guard for ExpectContinue<F>
+where
+    F: Fn(&Request<Body>) -> Result<(), R>,
+    R: Responder + Send,
+{
+    type Future = Ready<Result<Request<Body>, R>>;
+    type Responder = R;
+
+    fn validate(&'static self, req: Request<Body>) -> Self::Future {
+        if !req.expects_continue() {
+            return ready(Ok(req));
+        }
+
+        ready((self.check)(&req).map(|_| req))
+    }
+}
+
 /// Builder to apply guards onto the handler
 pub struct Builder<Chain: GuardChain> {
     chain: Chain,
@@ -66,6 +133,16 @@ pub trait GuardChain: Sync + Send {
 
     /// to avoid useless heap allocation if there is only a guard end chain
     fn is_end(&self) -> bool;
+
+    /// Number of guards in the chain, chains not overriding it count as a
+    /// single guard unless they are an end
+    fn count(&self) -> usize {
+        if self.is_end() {
+            0
+        } else {
+            1
+        }
+    }
 }
 
 #[doc(hidden)]
@@ -81,6 +158,11 @@ impl GuardChain for GuardChainEnd {
     fn is_end(&self) -> bool {
         true
     }
+
+    #[inline]
+    fn count(&self) -> usize {
+        0
+    }
 }
 
 #[doc(hidden)]
@@ -115,4 +197,53 @@ where
     fn is_end(&self) -> bool {
         false
     }
+
+    #[inline]
+    fn count(&self) -> usize {
+        1 + self.rest.count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::SaphirError,
+        prelude::{header, Bytes, Method, Server},
+        test_utils::TestClient,
+    };
+
+    #[tokio::test]
+    async fn expect_continue() {
+        async fn upload(mut req: Request) -> Result<u16, SaphirError> {
+            req.body_mut().take_as::<Bytes>().await?;
+            Ok(201)
+        }
+
+        let client = TestClient::new(Server::builder().configure_router(|r| {
+            r.route_with_guards("/upload", Method::PUT, upload, |g| {
+                g.apply(ExpectContinue::new(|req: &Request| {
+                    if req.headers().contains_key(header::AUTHORIZATION) {
+                        Ok(())
+                    } else {
+                        Err(401)
+                    }
+                }))
+            })
+        }));
+
+        let res = client.put("/upload").header(header::EXPECT, "100-continue").body("file").send().await.unwrap();
+        res.assert_status(401);
+        let res = client
+            .put("/upload")
+            .header(header::EXPECT, "100-continue")
+            .header(header::AUTHORIZATION, "Bearer token")
+            .body("file")
+            .send()
+            .await
+            .unwrap();
+        res.assert_status(201);
+        // Without the expectation the check is skipped
+        client.put("/upload").body("file").send().await.unwrap().assert_status(201);
+    }
 }
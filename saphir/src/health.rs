@@ -0,0 +1,332 @@
+//! Health checks of a server, exposed through a liveness and a readiness
+//! route answering with a json detail of every check.
+//!
+//! The liveness route tells whether the process is healthy, while the
+//! readiness route tells whether it should receive traffic. Readiness fails
+//! as soon as the server starts shutting down, so load balancers stop sending
+//! requests while the open connections drain.
+//!
+//! ```rust
+//! # use saphir::prelude::*;
+//! # async fn ping_database() -> Result<(), String> { Ok(()) }
+//! let server = Server::builder()
+//!     .configure_health(|h| {
+//!         h.readiness_check("database", || async { ping_database().await })
+//!             // Keep health probes out of the access logs
+//!             .skip_middlewares(true)
+//!     })
+//!     .build();
+//! ```
+//!
+//! A probe answers `200 OK` when every check passes, `503 Service
+//! Unavailable` otherwise:
+//!
+//! ```json
+//! {"status":"fail","draining":false,"checks":{"database":{"status":"fail","error":"connection refused"}}}
+//! ```
+
+use crate::{
+    body::Body,
+    request::Request,
+    response::Builder as ResponseBuilder,
+    router::{Builder as RouterBuilder, RouterChain},
+    server::ServerControl,
+};
+use futures::{
+    future::{join_all, BoxFuture},
+    Future, FutureExt,
+};
+use http::Method;
+use std::{sync::Arc, time::Duration};
+
+/// Default time a check can take before being considered failing
+pub const DEFAULT_CHECK_TIMEOUT_MS: u64 = 5000;
+
+type CheckFn = Box<dyn Fn() -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+struct Check {
+    name: String,
+    check: CheckFn,
+}
+
+/// The probes exposed by the health routes
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Probe {
+    Liveness,
+    Readiness,
+}
+
+/// Builder of the health routes of a server, see
+/// `server::Builder::configure_health`
+pub struct Builder {
+    liveness_path: String,
+    readiness_path: String,
+    liveness: Vec<Check>,
+    readiness: Vec<Check>,
+    check_timeout: Option<Duration>,
+    skip_middlewares: bool,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            liveness_path: "/health/live".to_string(),
+            readiness_path: "/health/ready".to_string(),
+            liveness: Vec::new(),
+            readiness: Vec::new(),
+            check_timeout: Some(Duration::from_millis(DEFAULT_CHECK_TIMEOUT_MS)),
+            skip_middlewares: false,
+        }
+    }
+}
+
+impl Builder {
+    /// Create a builder with the liveness route at `/health/live` and the
+    /// readiness route at `/health/ready`
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the path of the liveness route
+    #[inline]
+    pub fn liveness_path(mut self, path: &str) -> Self {
+        self.liveness_path = path.to_string();
+        self
+    }
+
+    /// Set the path of the readiness route
+    #[inline]
+    pub fn readiness_path(mut self, path: &str) -> Self {
+        self.readiness_path = path.to_string();
+        self
+    }
+
+    /// Register a check run by the liveness route. A failing liveness check
+    /// means the process should be restarted.
+    #[inline]
+    pub fn liveness_check<F, Fut>(mut self, name: &str, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.liveness.push(Check {
+            name: name.to_string(),
+            check: Box::new(move || check().boxed()),
+        });
+        self
+    }
+
+    /// Register a check run by the readiness route. A failing readiness check
+    /// means the server should not receive traffic for now, e.g. while a
+    /// dependency is unreachable.
+    #[inline]
+    pub fn readiness_check<F, Fut>(mut self, name: &str, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.readiness.push(Check {
+            name: name.to_string(),
+            check: Box::new(move || check().boxed()),
+        });
+        self
+    }
+
+    /// Set the time a check can take before being considered failing.
+    /// Default is `DEFAULT_CHECK_TIMEOUT_MS`.
+    #[inline]
+    pub fn check_timeout<T: Into<Option<Duration>>>(mut self, timeout: T) -> Self {
+        self.check_timeout = timeout.into();
+        self
+    }
+
+    /// Answer the health routes before the middleware chain, keeping probes
+    /// out of middlewares like access logs or authentication. Otherwise the
+    /// health routes are regular routes of the router.
+    #[inline]
+    pub fn skip_middlewares(mut self, skip: bool) -> Self {
+        self.skip_middlewares = skip;
+        self
+    }
+
+    pub(crate) fn build(self, control: Arc<ServerControl>) -> Arc<Health> {
+        let Builder {
+            liveness_path,
+            readiness_path,
+            liveness,
+            readiness,
+            check_timeout,
+            skip_middlewares,
+        } = self;
+
+        Arc::new(Health {
+            liveness_path,
+            readiness_path,
+            liveness,
+            readiness,
+            check_timeout,
+            skip_middlewares,
+            control,
+        })
+    }
+}
+
+pub(crate) struct Health {
+    liveness_path: String,
+    readiness_path: String,
+    liveness: Vec<Check>,
+    readiness: Vec<Check>,
+    check_timeout: Option<Duration>,
+    skip_middlewares: bool,
+    control: Arc<ServerControl>,
+}
+
+impl Health {
+    #[inline]
+    pub(crate) fn skip_middlewares(&self) -> bool {
+        self.skip_middlewares
+    }
+
+    /// Return the probe requested by `req`, if any
+    pub(crate) fn probe(&self, req: &Request<Body>) -> Option<Probe> {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return None;
+        }
+
+        let path = req.uri().path();
+        if path == self.liveness_path {
+            Some(Probe::Liveness)
+        } else if path == self.readiness_path {
+            Some(Probe::Readiness)
+        } else {
+            None
+        }
+    }
+
+    /// Add the health routes to the router, answering `GET` and `HEAD` like
+    /// `probe`
+    pub(crate) fn routes<C>(self: &Arc<Self>, mut router: RouterBuilder<C>) -> RouterBuilder<C>
+    where
+        C: RouterChain + Unpin + Send + Sync + 'static,
+    {
+        for method in &[Method::GET, Method::HEAD] {
+            let liveness = self.clone();
+            let readiness = self.clone();
+            router = router
+                .route(&self.liveness_path, method.clone(), move |_req: Request<Body>| {
+                    let health = liveness.clone();
+                    async move { health.respond(Probe::Liveness).await }
+                })
+                .route(&self.readiness_path, method.clone(), move |_req: Request<Body>| {
+                    let health = readiness.clone();
+                    async move { health.respond(Probe::Readiness).await }
+                });
+        }
+        router
+    }
+
+    /// Run the checks of `probe` and build the response
+    pub(crate) async fn respond(&self, probe: Probe) -> ResponseBuilder {
+        let checks = match probe {
+            Probe::Liveness => &self.liveness,
+            Probe::Readiness => &self.readiness,
+        };
+
+        let results = join_all(checks.iter().map(|c| self.run(c))).await;
+        let draining = probe == Probe::Readiness && self.control.draining();
+        let pass = !draining && results.iter().all(|r| r.is_ok());
+
+        let mut json = format!("{{\"status\":\"{}\"", if pass { "pass" } else { "fail" });
+        if probe == Probe::Readiness {
+            json.push_str(&format!(",\"draining\":{}", draining));
+        }
+        json.push_str(",\"checks\":{");
+        for (i, (check, result)) in checks.iter().zip(results).enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            match result {
+                Ok(()) => json.push_str(&format!("{}:{{\"status\":\"pass\"}}", json_string(&check.name))),
+                Err(e) => json.push_str(&format!("{}:{{\"status\":\"fail\",\"error\":{}}}", json_string(&check.name), json_string(&e))),
+            }
+        }
+        json.push_str("}}");
+
+        ResponseBuilder::new()
+            .status(if pass { 200 } else { 503 })
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(http::header::CACHE_CONTROL, "no-store")
+            .body(json)
+    }
+
+    async fn run(&self, check: &Check) -> Result<(), String> {
+        let res = match self.check_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, (check.check)())
+                .await
+                .unwrap_or_else(|_| Err("timed out".to_string())),
+            None => (check.check)().await,
+        };
+
+        if let Err(e) = &res {
+            warn!("Health check {} failed: {}", check.name, e);
+        }
+
+        res
+    }
+}
+
+/// Quote and escape `s` as a json string
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{server::Server, test_utils::TestClient};
+
+    #[test]
+    fn escape_json_string() {
+        assert_eq!(json_string("db"), "\"db\"");
+        assert_eq!(json_string("say \"hi\"\\\n"), "\"say \\\"hi\\\"\\\\\\n\"");
+        assert_eq!(json_string("\u{1}"), "\"\\u0001\"");
+    }
+
+    #[tokio::test]
+    async fn health_readiness_fails_when_draining() {
+        let client = TestClient::new(Server::builder().configure_health(|h| h.readiness_check("db", || async { Ok(()) })));
+        let res = client.get("/health/ready").send().await.unwrap();
+        res.assert_status(200).assert_header("content-type", "application/json");
+        assert_eq!(res.text(), r#"{"status":"pass","draining":false,"checks":{"db":{"status":"pass"}}}"#);
+
+        client.handle().shutdown(true);
+        let res = client.get("/health/ready").send().await.unwrap();
+        res.assert_status(503);
+        assert_eq!(res.text(), r#"{"status":"fail","draining":true,"checks":{"db":{"status":"pass"}}}"#);
+        client.get("/health/live").send().await.unwrap().assert_status(200);
+    }
+
+    #[tokio::test]
+    async fn health_answers_head() {
+        for skip_middlewares in &[false, true] {
+            let client = TestClient::new(Server::builder().configure_health(|h| h.skip_middlewares(*skip_middlewares)));
+            client.head("/health/live").send().await.unwrap().assert_status(200);
+            client.head("/health/ready").send().await.unwrap().assert_status(200);
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use crate::{request::Request, response::Response, router::Router};
+use crate::{error::SaphirError, request::Request, response::Response, router::Router};
+use std::sync::Arc;
 
 #[cfg(feature = "operation")]
 pub static OPERATION_ID_HEADER: &str = "Operation-Id";
@@ -170,6 +171,14 @@ impl Default for RouteId {
 pub struct HandlerMetadata {
     pub route_id: RouteId,
     pub name: Option<&'static str>,
+    /// Path template of the resolved route, e.g. `/users/<id>`
+    pub route: Option<Arc<str>>,
+    /// Timeout of the handler in milliseconds, overriding the one of the
+    /// listener. `Some(None)` runs the handler without any timeout
+    pub timeout_ms: Option<Option<u64>>,
+    /// Maximum number of bytes of the request body, overriding the one of
+    /// the listener
+    pub body_limit: Option<usize>,
 }
 
 impl HandlerMetadata {
@@ -177,6 +186,9 @@ impl HandlerMetadata {
         HandlerMetadata {
             route_id: Default::default(),
             name: None,
+            route: None,
+            timeout_ms: None,
+            body_limit: None,
         }
     }
 
@@ -184,6 +196,9 @@ impl HandlerMetadata {
         HandlerMetadata {
             route_id: RouteId::Error(405),
             name: None,
+            route: None,
+            timeout_ms: None,
+            body_limit: None,
         }
     }
 }
@@ -204,21 +219,29 @@ pub struct HttpContext {
     pub operation_id: crate::http_context::operation::OperationId,
     pub metadata: HandlerMetadata,
     pub(crate) router: Option<Router>,
+    /// Number of router scopes whose middlewares already ran
+    pub(crate) scope_depth: usize,
 }
 
 impl HttpContext {
-    pub(crate) fn new(request: Request, router: Router, metadata: HandlerMetadata) -> Self {
+    pub(crate) fn new(mut request: Request, router: Router, metadata: HandlerMetadata) -> Self {
+        request.set_router(Some(router.clone()));
+
         #[cfg(not(feature = "operation"))]
         {
             let state = State::Before(Box::new(request));
             let router = Some(router);
-            HttpContext { state, metadata, router }
+            HttpContext {
+                state,
+                metadata,
+                router,
+                scope_depth: 0,
+            }
         }
 
         #[cfg(feature = "operation")]
         {
             use std::str::FromStr;
-            let mut request = request;
             let operation_id = request
                 .headers()
                 .get(OPERATION_ID_HEADER)
@@ -233,6 +256,7 @@ impl HttpContext {
                 router,
                 operation_id,
                 metadata,
+                scope_depth: 0,
             }
         }
     }
@@ -244,9 +268,24 @@ impl HttpContext {
             metadata: self.metadata.clone(),
             #[cfg(feature = "operation")]
             operation_id: self.operation_id,
+            scope_depth: self.scope_depth,
         }
     }
 
+    /// Build the url of the route of the handler named `name`, see
+    /// [`Router::url_for`](../router/struct.Router.html#method.url_for)
+    pub fn url_for<I, K, V>(&self, name: &str, params: I) -> Result<String, SaphirError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: ToString,
+    {
+        self.router
+            .as_ref()
+            .ok_or_else(|| SaphirError::Other("The request was not received by a router".to_string()))?
+            .url_for(name, params)
+    }
+
     /// Explicitly set the inner state to `Before` with the given response
     pub fn before(&mut self, request: Request) {
         self.state = State::Before(Box::new(request))
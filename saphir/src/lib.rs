@@ -47,7 +47,7 @@
 //!         .build();
 //!
 //!     // Start server with
-//!     // server.run().await
+//!     // server.run().await?;
 //! #    Ok(())
 //! }
 //! ```
@@ -64,6 +64,10 @@
 //! - `json`  : Add the `Json` wrapper type to simplify working with json data
 //! - `form`  : Add the `Form` wrapper type to simplify working with urlencoded
 //!   data
+//! - `metrics` : Add the `Metrics` registry, collecting request, connection and
+//!   file cache metrics in the Prometheus text exposition format
+//! - `test-utils` : Add the `TestClient` to send requests to a server stack
+//!   without opening sockets
 //!
 //! *_More feature will be added in the future_*
 #![allow(clippy::match_like_matches_macro)]
@@ -84,20 +88,28 @@ pub mod extension;
 ///
 #[cfg(feature = "file")]
 pub mod file;
+/// Client resolution through trusted reverse proxies
+pub mod forwarded;
 ///
 pub mod guard;
 /// Definition of types which can handle an http request
 pub mod handler;
+/// Liveness and readiness routes backed by health checks
+pub mod health;
 /// Context enveloping every request <-> response
 pub mod http_context;
 /// Saphir macro for code generation
 #[cfg(feature = "macro")]
 pub mod macros;
+/// Runtime metrics in the Prometheus text exposition format
+#[cfg(feature = "metrics")]
+pub mod metrics;
 ///
 pub mod middleware;
 /// The async Multipart Form-Data representation
 #[cfg(feature = "multipart")]
 pub mod multipart;
+mod proxy_protocol;
 ///
 #[cfg(feature = "redirect")]
 pub mod redirect;
@@ -111,6 +123,12 @@ pub mod response;
 pub mod router;
 /// Server implementation and default runtime
 pub mod server;
+/// In-process client to test a server stack, using feature `test-utils`
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+/// Tls session information, using feature `https`
+#[cfg(feature = "https")]
+pub mod tls;
 ///
 pub mod utils;
 ///
@@ -189,6 +207,8 @@ pub mod prelude {
     ///
     pub use crate::response::Response;
     ///
+    pub use crate::router::RouteOptions;
+    ///
     pub use crate::server::Server;
     ///
     pub use crate::server::Stack;
@@ -3,12 +3,16 @@
 //! # The `#[controller]` Macro
 //!
 //! This macro is an attribute macro that need to be place on the `impl block`
-//! of a Saphir controller. It has 3 optionnal parameters:
+//! of a Saphir controller. It has 5 optionnal parameters:
 //! - `prefix="<pre>"` : This will prefix any controller route by the specified
 //!   route prefix
 //! - `version=<u16>`  : This will insert the `/v#` path segment between the
 //!   prefix and the base controller route
 //! - `name="<name>"`  : This will route the controller at /<name>.
+//! - `timeout=<ms>`   : This will set the request timeout of every endpoint of
+//!   the controller, overriding the `request_timeout` of the listener.
+//! - `body_limit=<size>` : This will set the maximum size of the request body
+//!   of every endpoint of the controller, e.g. `body_limit=1MB`.
 //!
 //! If none of these are used, the controller will be routed at its own name, in
 //! lowercase, with the controller keyword trimmed.
@@ -151,6 +155,19 @@
 //!   the data that will be passed to the guard function. this function takes a
 //!   reference of the controller type it is used in.
 //!
+//! ## The `#[timeout(<ms>)] Attribute`
+//! This will set the request timeout of the endpoint in milliseconds,
+//! overriding both the `timeout` of the controller and the `request_timeout`
+//! of the listener. E.g. `#[timeout(300000)]` lets an upload endpoint run for
+//! 5 minutes.
+//!
+//! ## The `#[body_limit(<size>)] Attribute`
+//! This will set the maximum size of the request body of the endpoint,
+//! overriding both the `body_limit` of the controller and the
+//! `request_body_max_bytes` of the listener. The size is in bytes, or in `KB`,
+//! `MB` or `GB` (multiples of 1024). E.g. `#[body_limit(10MB)]`. A larger
+//! body is answered with `413 Payload Too Large`.
+//!
 //! # Type Attributes (Struct & Enum)
 //! These attributes can be added on top of a `struct` or `enum` definition.
 //!
@@ -0,0 +1,532 @@
+//! Runtime metrics of a server, exposed in the Prometheus text exposition
+//! format.
+//!
+//! A `Metrics` registry collects:
+//! - the number of requests, by handler, method and status code
+//! - the latency histogram of the requests, by handler and method
+//! - the requests in-flight
+//! - the bytes of the request and response bodies, when their size is known
+//! - the open connections, once the registry is given to the server builder
+//! - the hits, misses and size of the `FileCache`, once the registry is given
+//!   to the `FileMiddlewareBuilder`
+//!
+//! Requests are labelled with the name of their handler, or the template of
+//! their route, e.g. `/users/<id>`, for unnamed handlers. Requests matching no
+//! route are labelled `unmatched`. Methods outside of the standard ones are
+//! labelled `other`, so arbitrary methods cannot grow the registry.
+//!
+//! ```rust
+//! # use saphir::prelude::*;
+//! use saphir::metrics::Metrics;
+//!
+//! let metrics = Metrics::new();
+//! let server = Server::builder()
+//!     .metrics(&metrics)
+//!     .configure_router(|r| r.route("/metrics", Method::GET, metrics.clone()))
+//!     // Applied last, the middleware observes every other middleware
+//!     .configure_middlewares(|m| m.apply(metrics.middleware(), vec!["/"], None))
+//!     .build();
+//! ```
+
+use crate::{
+    body::Body,
+    error::SaphirError,
+    handler::Handler,
+    http_context::HttpContext,
+    middleware::{Middleware, MiddlewareChain},
+    request::Request,
+    response::Builder as ResponseBuilder,
+};
+use futures::{
+    future::{ready, BoxFuture, Ready},
+    FutureExt,
+};
+use http::{header::CONTENT_LENGTH, HeaderMap, Method};
+use http_body::Body as HttpBody;
+use parking_lot::Mutex;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Default buckets of the latency histogram, in seconds
+pub const DEFAULT_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Content type of the Prometheus text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+const UNMATCHED: &str = "unmatched";
+
+const OTHER_METHOD: &str = "other";
+
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::HEAD => "HEAD",
+        Method::OPTIONS => "OPTIONS",
+        Method::CONNECT => "CONNECT",
+        Method::PATCH => "PATCH",
+        Method::TRACE => "TRACE",
+        _ => OTHER_METHOD,
+    }
+}
+
+/// Registry of the metrics of a server. Clones share the same metrics.
+///
+/// The registry is also a handler rendering the metrics, to register as a
+/// route of the server.
+#[derive(Clone)]
+pub struct Metrics {
+    inner: Arc<Registry>,
+}
+
+struct Registry {
+    buckets: Vec<f64>,
+    handlers: Mutex<BTreeMap<(String, String), HandlerStats>>,
+    in_flight: AtomicU64,
+    open_connections: AtomicU64,
+    connections: AtomicU64,
+    file_cache_hits: AtomicU64,
+    file_cache_misses: AtomicU64,
+    file_cache_bytes: AtomicU64,
+}
+
+/// Metrics of the requests of a single handler and method
+struct HandlerStats {
+    statuses: BTreeMap<u16, u64>,
+    /// Number of requests per bucket, not cumulated
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+    body_in: u64,
+    body_out: u64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::with_buckets(DEFAULT_BUCKETS.to_vec())
+    }
+}
+
+impl Metrics {
+    /// Create a registry using the `DEFAULT_BUCKETS` for the latency
+    /// histogram
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry using `buckets`, in seconds, for the latency
+    /// histogram
+    pub fn with_buckets(mut buckets: Vec<f64>) -> Self {
+        buckets.retain(|b| b.is_finite());
+        buckets.sort_by(|a, b| a.partial_cmp(b).expect("finite buckets"));
+        buckets.dedup();
+
+        Metrics {
+            inner: Arc::new(Registry {
+                buckets,
+                handlers: Mutex::new(BTreeMap::new()),
+                in_flight: AtomicU64::new(0),
+                open_connections: AtomicU64::new(0),
+                connections: AtomicU64::new(0),
+                file_cache_hits: AtomicU64::new(0),
+                file_cache_misses: AtomicU64::new(0),
+                file_cache_bytes: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Return a middleware recording the requests in this registry.
+    ///
+    /// Errors returned by the rest of the chain are turned into their
+    /// response, to record their status. Requests cancelled before their
+    /// response, because of a timeout or a closed connection, are recorded
+    /// with the `408` status.
+    #[inline]
+    pub fn middleware(&self) -> MetricsMiddleware {
+        MetricsMiddleware { metrics: self.clone() }
+    }
+
+    /// Render every metric in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let registry = &self.inner;
+        let mut out = String::new();
+
+        let handlers = registry.handlers.lock();
+        header(&mut out, "saphir_http_requests_total", "counter", "Number of http requests handled");
+        for ((handler, method), stats) in handlers.iter() {
+            for (status, count) in &stats.statuses {
+                let _ = writeln!(
+                    out,
+                    "saphir_http_requests_total{{handler={},method={},status=\"{}\"}} {}",
+                    label(handler),
+                    label(method),
+                    status,
+                    count
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "saphir_http_request_duration_seconds",
+            "histogram",
+            "Time spent handling http requests",
+        );
+        for ((handler, method), stats) in handlers.iter() {
+            let labels = format!("handler={},method={}", label(handler), label(method));
+            let mut cumulated = 0;
+            for (bucket, count) in registry.buckets.iter().zip(&stats.buckets) {
+                cumulated += count;
+                let _ = writeln!(out, "saphir_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}", labels, bucket, cumulated);
+            }
+            let _ = writeln!(out, "saphir_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, stats.count);
+            let _ = writeln!(out, "saphir_http_request_duration_seconds_sum{{{}}} {}", labels, stats.sum);
+            let _ = writeln!(out, "saphir_http_request_duration_seconds_count{{{}}} {}", labels, stats.count);
+        }
+
+        header(
+            &mut out,
+            "saphir_http_request_body_bytes_total",
+            "counter",
+            "Bytes of the request bodies received",
+        );
+        for ((handler, method), stats) in handlers.iter() {
+            let _ = writeln!(
+                out,
+                "saphir_http_request_body_bytes_total{{handler={},method={}}} {}",
+                label(handler),
+                label(method),
+                stats.body_in
+            );
+        }
+
+        header(
+            &mut out,
+            "saphir_http_response_body_bytes_total",
+            "counter",
+            "Bytes of the response bodies sent",
+        );
+        for ((handler, method), stats) in handlers.iter() {
+            let _ = writeln!(
+                out,
+                "saphir_http_response_body_bytes_total{{handler={},method={}}} {}",
+                label(handler),
+                label(method),
+                stats.body_out
+            );
+        }
+        drop(handlers);
+
+        let scalars = [
+            (
+                "saphir_http_requests_in_flight",
+                "gauge",
+                "Number of http requests being handled",
+                &registry.in_flight,
+            ),
+            ("saphir_connections_open", "gauge", "Number of open connections", &registry.open_connections),
+            ("saphir_connections_total", "counter", "Number of connections accepted", &registry.connections),
+            (
+                "saphir_file_cache_hits_total",
+                "counter",
+                "Number of files served from the file cache",
+                &registry.file_cache_hits,
+            ),
+            (
+                "saphir_file_cache_misses_total",
+                "counter",
+                "Number of files read outside of the file cache",
+                &registry.file_cache_misses,
+            ),
+            (
+                "saphir_file_cache_size_bytes",
+                "gauge",
+                "Bytes stored in the file cache",
+                &registry.file_cache_bytes,
+            ),
+        ];
+        for (name, kind, help, value) in scalars.iter() {
+            header(&mut out, name, kind, help);
+            let _ = writeln!(out, "{} {}", name, value.load(Ordering::SeqCst));
+        }
+
+        out
+    }
+
+    fn record(&self, handler: String, method: &Method, status: u16, elapsed: Duration, body_in: u64, body_out: u64) {
+        let registry = &self.inner;
+        let elapsed = elapsed.as_secs_f64();
+
+        let mut handlers = registry.handlers.lock();
+        let stats = handlers.entry((handler, method_label(method).to_string())).or_insert_with(|| HandlerStats {
+            statuses: BTreeMap::new(),
+            buckets: vec![0; registry.buckets.len()],
+            sum: 0.0,
+            count: 0,
+            body_in: 0,
+            body_out: 0,
+        });
+
+        *stats.statuses.entry(status).or_insert(0) += 1;
+        if let Some(i) = registry.buckets.iter().position(|b| elapsed <= *b) {
+            stats.buckets[i] += 1;
+        }
+        stats.sum += elapsed;
+        stats.count += 1;
+        stats.body_in += body_in;
+        stats.body_out += body_out;
+    }
+
+    /// Count a connection as open until the returned guard is dropped
+    pub(crate) fn open_connection(&self) -> OpenConnection {
+        self.inner.connections.fetch_add(1, Ordering::SeqCst);
+        self.inner.open_connections.fetch_add(1, Ordering::SeqCst);
+        OpenConnection(self.inner.clone())
+    }
+
+    #[cfg(feature = "file")]
+    pub(crate) fn file_cache_hit(&self) {
+        self.inner.file_cache_hits.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[cfg(feature = "file")]
+    pub(crate) fn file_cache_miss(&self) {
+        self.inner.file_cache_misses.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[cfg(feature = "file")]
+    pub(crate) fn file_cache_insert(&self, size: u64) {
+        self.inner.file_cache_bytes.fetch_add(size, Ordering::SeqCst);
+    }
+
+    async fn observe(&self, ctx: HttpContext, chain: &'static dyn MiddlewareChain) -> Result<HttpContext, SaphirError> {
+        let mut request = ObservedRequest::new(self, &ctx);
+        let err_ctx = ctx.clone_with_empty_state();
+
+        let ctx = match chain.next(ctx).await {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                e.log(&err_ctx);
+                let res = e.response_builder(ResponseBuilder::new(), &err_ctx);
+                let mut ctx = err_ctx;
+                match res.build() {
+                    Ok(res) => ctx.after(res),
+                    Err(e) => {
+                        request.status = 500;
+                        return Err(e);
+                    }
+                }
+                ctx
+            }
+        };
+
+        if let Some(res) = ctx.state.response() {
+            request.status = res.status().as_u16();
+            request.body_out = body_size(res.body(), res.headers());
+        }
+
+        Ok(ctx)
+    }
+}
+
+impl Handler<Body> for Metrics {
+    type Future = Ready<ResponseBuilder>;
+    type Responder = ResponseBuilder;
+
+    fn handle(&self, _req: Request<Body>) -> Self::Future {
+        ready(
+            ResponseBuilder::new()
+                .status(200)
+                .header(http::header::CONTENT_TYPE, CONTENT_TYPE)
+                .body(self.render()),
+        )
+    }
+}
+
+/// Middleware recording the requests in a `Metrics` registry, see
+/// `Metrics::middleware`
+pub struct MetricsMiddleware {
+    metrics: Metrics,
+}
+
+impl Middleware for MetricsMiddleware {
+    fn next(&'static self, ctx: HttpContext, chain: &'static dyn MiddlewareChain) -> BoxFuture<'static, Result<HttpContext, SaphirError>> {
+        self.metrics.observe(ctx, chain).boxed()
+    }
+}
+
+/// Request in-flight, recorded once dropped
+struct ObservedRequest {
+    metrics: Metrics,
+    handler: Option<String>,
+    method: Method,
+    start: Instant,
+    status: u16,
+    body_in: u64,
+    body_out: u64,
+}
+
+impl ObservedRequest {
+    fn new(metrics: &Metrics, ctx: &HttpContext) -> Self {
+        metrics.inner.in_flight.fetch_add(1, Ordering::SeqCst);
+
+        let handler = ctx
+            .metadata
+            .name
+            .map(str::to_string)
+            .or_else(|| ctx.metadata.route.as_deref().map(str::to_string));
+        let (method, body_in) = ctx
+            .state
+            .request()
+            .map(|req| (req.method().clone(), body_size(req.body(), req.headers())))
+            .unwrap_or_default();
+
+        ObservedRequest {
+            metrics: metrics.clone(),
+            handler,
+            method,
+            start: Instant::now(),
+            status: 408,
+            body_in,
+            body_out: 0,
+        }
+    }
+}
+
+impl Drop for ObservedRequest {
+    fn drop(&mut self) {
+        self.metrics.inner.in_flight.fetch_sub(1, Ordering::SeqCst);
+        let handler = self.handler.take().unwrap_or_else(|| UNMATCHED.to_string());
+        self.metrics
+            .record(handler, &self.method, self.status, self.start.elapsed(), self.body_in, self.body_out);
+    }
+}
+
+/// Keep a connection counted as open until dropped
+pub(crate) struct OpenConnection(Arc<Registry>);
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        self.0.open_connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Size of a body, when known from its stream or its headers
+fn body_size(body: &Body, headers: &HeaderMap) -> u64 {
+    HttpBody::size_hint(body)
+        .exact()
+        .or_else(|| headers.get(CONTENT_LENGTH)?.to_str().ok()?.parse().ok())
+        .unwrap_or(0)
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Quote and escape `value` as a label value
+fn label(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{router::RouteOptions, server::Server, test_utils::TestClient};
+
+    #[test]
+    fn render_histogram() {
+        let metrics = Metrics::with_buckets(vec![1.0, 0.1, f64::INFINITY]);
+        metrics.record("/users/<id>".to_string(), &Method::GET, 200, Duration::from_millis(50), 0, 12);
+        metrics.record("/users/<id>".to_string(), &Method::GET, 404, Duration::from_millis(500), 0, 3);
+        metrics.record("/users/<id>".to_string(), &Method::GET, 200, Duration::from_secs(2), 0, 12);
+
+        let out = metrics.render();
+        assert!(out.contains("saphir_http_requests_total{handler=\"/users/<id>\",method=\"GET\",status=\"200\"} 2\n"));
+        assert!(out.contains("saphir_http_requests_total{handler=\"/users/<id>\",method=\"GET\",status=\"404\"} 1\n"));
+        assert!(out.contains("saphir_http_request_duration_seconds_bucket{handler=\"/users/<id>\",method=\"GET\",le=\"0.1\"} 1\n"));
+        assert!(out.contains("saphir_http_request_duration_seconds_bucket{handler=\"/users/<id>\",method=\"GET\",le=\"1\"} 2\n"));
+        assert!(out.contains("saphir_http_request_duration_seconds_bucket{handler=\"/users/<id>\",method=\"GET\",le=\"+Inf\"} 3\n"));
+        assert!(out.contains("saphir_http_request_duration_seconds_count{handler=\"/users/<id>\",method=\"GET\"} 3\n"));
+        assert!(out.contains("saphir_http_response_body_bytes_total{handler=\"/users/<id>\",method=\"GET\"} 27\n"));
+        assert!(out.contains("# TYPE saphir_connections_open gauge\nsaphir_connections_open 0\n"));
+    }
+
+    #[test]
+    fn label_unknown_methods_as_other() {
+        let metrics = Metrics::new();
+        for i in 0..10 {
+            let method = Method::from_bytes(format!("FOO{}", i).as_bytes()).unwrap();
+            metrics.record(UNMATCHED.to_string(), &method, 405, Duration::from_millis(1), 0, 0);
+        }
+
+        assert_eq!(metrics.inner.handlers.lock().len(), 1);
+        assert!(metrics
+            .render()
+            .contains("saphir_http_requests_total{handler=\"unmatched\",method=\"other\",status=\"405\"} 10\n"));
+    }
+
+    #[test]
+    fn escape_label() {
+        assert_eq!(label("index"), "\"index\"");
+        assert_eq!(label("say \"hi\"\\\n"), "\"say \\\"hi\\\"\\\\\\n\"");
+    }
+
+    #[tokio::test]
+    async fn metrics_record_requests() {
+        async fn echo(mut req: Request) -> (u16, Vec<u8>) {
+            let body = req.body_mut().take_as::<Vec<u8>>().await.unwrap_or_default();
+            (200, body)
+        }
+
+        async fn slow(_req: Request) -> u16 {
+            tokio::time::delay_for(std::time::Duration::from_millis(200)).await;
+            200
+        }
+
+        let metrics = Metrics::new();
+        let client = TestClient::new(
+            Server::builder()
+                .configure_router(|r| {
+                    r.route("/echo", Method::POST, echo)
+                        .route_with_options("/slow", Method::GET, slow, RouteOptions::new().timeout(50))
+                        .route("/metrics", Method::GET, metrics.clone())
+                })
+                .configure_middlewares(|m| m.apply(metrics.middleware(), vec!["/"], None)),
+        );
+        client.post("/echo").body("ping").send().await.unwrap().assert_status(200);
+        client.get("/slow").send().await.unwrap().assert_status(408);
+        client.get("/missing").send().await.unwrap().assert_status(404);
+
+        let res = client.get("/metrics").send().await.unwrap();
+        res.assert_status(200).assert_header("content-type", CONTENT_TYPE);
+        let text = res.text();
+        assert!(text.contains("saphir_http_requests_total{handler=\"/echo\",method=\"POST\",status=\"200\"} 1\n"));
+        assert!(text.contains("saphir_http_requests_total{handler=\"/slow\",method=\"GET\",status=\"408\"} 1\n"));
+        assert!(text.contains("saphir_http_requests_total{handler=\"unmatched\",method=\"GET\",status=\"404\"} 1\n"));
+        assert!(text.contains("saphir_http_request_body_bytes_total{handler=\"/echo\",method=\"POST\"} 4\n"));
+        assert!(text.contains("saphir_http_response_body_bytes_total{handler=\"/echo\",method=\"POST\"} 4\n"));
+        assert!(text.contains("saphir_http_requests_in_flight 1\n"));
+    }
+}
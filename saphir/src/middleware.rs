@@ -35,6 +35,28 @@
 //! }
 //! ```
 //!
+//! Middlewares run before the request body is read, so a middleware returning
+//! an error for a request sent with `Expect: 100-continue` answers it before
+//! the client streams its body:
+//!
+//! ```rust
+//! # use saphir::prelude::*;
+//! # struct CustomData;
+//! #
+//! async fn authorize_uploads(data: &CustomData, ctx: HttpContext, chain: &dyn MiddlewareChain) -> Result<HttpContext, SaphirError> {
+//!     let unauthorized = ctx
+//!         .state
+//!         .request()
+//!         .map(|req| req.expects_continue() && !req.headers().contains_key(header::AUTHORIZATION))
+//!         .unwrap_or(false);
+//!     if unauthorized {
+//!         return Err(SaphirError::responder(401));
+//!     }
+//!
+//!     chain.next(ctx).await
+//! }
+//! ```
+//!
 //! *SAFETY NOTICE*
 //!
 //! Inside the middleware chain we need a little bit of unsafe code. This code
@@ -171,7 +193,7 @@ impl MiddlewareChain for MiddleChainEnd {
     #[inline]
     fn next(&self, mut ctx: HttpContext) -> BoxFuture<'static, Result<HttpContext, SaphirError>> {
         async {
-            let router = ctx.router.take().ok_or(SaphirError::Internal(InternalError::Stack))?;
+            let router = ctx.router.clone().ok_or(SaphirError::Internal(InternalError::Stack))?;
             router.dispatch(ctx).await
         }
         .boxed()
@@ -1,4 +1,4 @@
-use std::{fmt::Debug, str::FromStr, sync::Arc};
+use std::{error::Error as _, fmt::Debug, str::FromStr, sync::Arc};
 
 use futures::TryStreamExt;
 use futures_util::stream::Stream;
@@ -8,6 +8,7 @@ use parking_lot::Mutex;
 
 use crate::{
     body::{Body, Bytes},
+    error::SaphirError,
     http_context::HttpContext,
     multipart::parser::ParseFieldError,
     request::{FromRequest, Request},
@@ -55,7 +56,16 @@ impl Responder for MultipartError {
         };
 
         debug!("{}Unable to parse multipart data: {:?}", op_id, &self);
-        builder.status(400)
+        // The body limit of the route is reported through the hyper body
+        let too_large = match &self {
+            MultipartError::Hyper(e) => e
+                .source()
+                .and_then(|e| e.downcast_ref::<SaphirError>())
+                .map(|e| matches!(e, SaphirError::PayloadTooLarge(_)))
+                .unwrap_or(false),
+            _ => false,
+        };
+        builder.status(if too_large { 413 } else { 400 })
     }
 }
 
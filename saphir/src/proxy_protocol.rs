@@ -0,0 +1,205 @@
+//! Parsing of the HAProxy PROXY protocol header, version 1 (text) and 2
+//! (binary), sent by load balancers before the proxied connection data.
+//!
+//! See https://www.haproxy.org/download/2.3/doc/proxy-protocol.txt
+use crate::error::SaphirError;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V1_MAX_LEN: usize = 107;
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+fn invalid(reason: &str) -> SaphirError {
+    SaphirError::Other(format!("Invalid PROXY protocol header: {}", reason))
+}
+
+/// Read the PROXY protocol header at the start of `stream`, without consuming
+/// any byte past it.
+///
+/// Returns the address of the client, or `None` when the header does not
+/// carry one, e.g. for health checks of the proxy itself.
+pub(crate) async fn read_header<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Option<SocketAddr>, SaphirError> {
+    // Both the signature of v2 and the shortest v1 header are at least 12 bytes
+    let mut header = vec![0u8; V2_SIGNATURE.len()];
+    stream.read_exact(&mut header).await?;
+
+    if header.as_slice() == V2_SIGNATURE {
+        let mut fixed = [0u8; 4];
+        stream.read_exact(&mut fixed).await?;
+        let len = u16::from_be_bytes([fixed[2], fixed[3]]) as usize;
+        let mut payload = vec![0u8; len];
+        stream.read_exact(&mut payload).await?;
+        return parse_v2(fixed[0], fixed[1], &payload);
+    }
+
+    if !header.starts_with(V1_PREFIX) {
+        return Err(invalid("missing signature"));
+    }
+
+    // The v1 header is a single line, read it byte per byte to leave the
+    // following data untouched
+    while !header.ends_with(b"\r\n") {
+        if header.len() >= V1_MAX_LEN {
+            return Err(invalid("line too long"));
+        }
+        header.push(stream.read_u8().await?);
+    }
+
+    parse_v1(&header[..header.len() - 2])
+}
+
+fn parse_v1(line: &[u8]) -> Result<Option<SocketAddr>, SaphirError> {
+    let line = std::str::from_utf8(line).map_err(|_| invalid("not ascii"))?;
+    let mut parts = line.split(' ').skip(1);
+
+    let is_v4 = match parts.next() {
+        Some("TCP4") => true,
+        Some("TCP6") => false,
+        Some("UNKNOWN") => return Ok(None),
+        _ => return Err(invalid("unknown protocol")),
+    };
+
+    let mut next = || parts.next().ok_or_else(|| invalid("missing field"));
+    let source: IpAddr = next()?.parse().map_err(|_| invalid("invalid source address"))?;
+    let destination: IpAddr = next()?.parse().map_err(|_| invalid("invalid destination address"))?;
+    let source_port: u16 = next()?.parse().map_err(|_| invalid("invalid source port"))?;
+    let _destination_port: u16 = next()?.parse().map_err(|_| invalid("invalid destination port"))?;
+
+    if parts.next().is_some() {
+        return Err(invalid("trailing data"));
+    }
+
+    if source.is_ipv4() != is_v4 || destination.is_ipv4() != is_v4 {
+        return Err(invalid("address family mismatch"));
+    }
+
+    Ok(Some(SocketAddr::new(source, source_port)))
+}
+
+fn parse_v2(version_command: u8, family: u8, payload: &[u8]) -> Result<Option<SocketAddr>, SaphirError> {
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported version"));
+    }
+
+    match version_command & 0x0F {
+        // LOCAL, the connection was initiated by the proxy itself
+        0x0 => return Ok(None),
+        0x1 => {}
+        _ => return Err(invalid("unknown command")),
+    }
+
+    match family >> 4 {
+        0x1 => {
+            if payload.len() < 12 {
+                return Err(invalid("truncated ipv4 addresses"));
+            }
+            let ip = Ipv4Addr::new(payload[0], payload[1], payload[2], payload[3]);
+            let port = u16::from_be_bytes([payload[8], payload[9]]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+        }
+        0x2 => {
+            if payload.len() < 36 {
+                return Err(invalid("truncated ipv6 addresses"));
+            }
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&payload[..16]);
+            let port = u16::from_be_bytes([payload[32], payload[33]]);
+            Ok(Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(octets)), port)))
+        }
+        // AF_UNSPEC & AF_UNIX, no usable client address
+        0x0 | 0x3 => Ok(None),
+        _ => Err(invalid("unknown address family")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v2(command: u8, family: u8, payload: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(command);
+        header.push(family);
+        header.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        header.extend_from_slice(payload);
+        header
+    }
+
+    #[tokio::test]
+    async fn read_v1_header() {
+        let mut stream: &[u8] = b"PROXY TCP4 192.168.0.1 10.0.0.1 56324 443\r\nGET / HTTP/1.1\r\n";
+        let addr = read_header(&mut stream).await.unwrap();
+        assert_eq!(addr, Some("192.168.0.1:56324".parse().unwrap()));
+        assert_eq!(stream, b"GET / HTTP/1.1\r\n");
+
+        let mut stream: &[u8] = b"PROXY TCP6 2001:db8::1 2001:db8::2 4000 80\r\n";
+        assert_eq!(read_header(&mut stream).await.unwrap(), Some("[2001:db8::1]:4000".parse().unwrap()));
+
+        let mut stream: &[u8] = b"PROXY UNKNOWN\r\nGET";
+        assert_eq!(read_header(&mut stream).await.unwrap(), None);
+        assert_eq!(stream, b"GET");
+    }
+
+    #[tokio::test]
+    async fn reject_invalid_v1_header() {
+        let invalid: &[&[u8]] = &[
+            b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            b"PROXY TCP4 192.168.0.1 10.0.0.1 56324\r\n",
+            b"PROXY TCP4 2001:db8::1 10.0.0.1 56324 443\r\n",
+            b"PROXY TCP4 192.168.0.1 10.0.0.1 56324 99999\r\n",
+            b"PROXY UDP4 192.168.0.1 10.0.0.1 56324 443\r\n",
+            b"PROXY TCP4 192.168.0.1 10.0.0.1 56324 443",
+        ];
+        for header in invalid {
+            let mut stream = *header;
+            assert!(read_header(&mut stream).await.is_err(), "{:?}", String::from_utf8_lossy(header));
+        }
+
+        let mut long = b"PROXY TCP4 ".to_vec();
+        long.extend_from_slice(&[b'1'; 200]);
+        assert!(read_header(&mut long.as_slice()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn read_v2_header() {
+        let mut payload = vec![192, 168, 0, 1, 10, 0, 0, 1];
+        payload.extend_from_slice(&56324u16.to_be_bytes());
+        payload.extend_from_slice(&443u16.to_be_bytes());
+        // A TLV extension, ignored
+        payload.extend_from_slice(&[0x04, 0x00, 0x01, 0xFF]);
+        let mut data = v2(0x21, 0x11, &payload);
+        data.extend_from_slice(b"GET");
+
+        let mut stream = data.as_slice();
+        assert_eq!(read_header(&mut stream).await.unwrap(), Some("192.168.0.1:56324".parse().unwrap()));
+        assert_eq!(stream, b"GET");
+
+        let mut payload = "2001:db8::1".parse::<Ipv6Addr>().unwrap().octets().to_vec();
+        payload.extend_from_slice(&"2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        payload.extend_from_slice(&4000u16.to_be_bytes());
+        payload.extend_from_slice(&80u16.to_be_bytes());
+        let data = v2(0x21, 0x21, &payload);
+        assert_eq!(read_header(&mut data.as_slice()).await.unwrap(), Some("[2001:db8::1]:4000".parse().unwrap()));
+
+        let data = v2(0x20, 0x00, &[]);
+        assert_eq!(read_header(&mut data.as_slice()).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn reject_invalid_v2_header() {
+        let invalid = vec![
+            v2(0x11, 0x11, &[0; 12]),
+            v2(0x22, 0x11, &[0; 12]),
+            v2(0x21, 0x11, &[0; 8]),
+            v2(0x21, 0x21, &[0; 12]),
+        ];
+        for header in invalid {
+            assert!(read_header(&mut header.as_slice()).await.is_err());
+        }
+
+        let mut truncated = v2(0x21, 0x11, &[0; 12]);
+        truncated.truncate(20);
+        assert!(read_header(&mut truncated.as_slice()).await.is_err());
+    }
+}
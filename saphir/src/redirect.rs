@@ -43,6 +43,21 @@ pub struct Builder {
 }
 
 impl Builder {
+    /// Set the location of the redirection, e.g. the url of a route built
+    /// with `Request::url_for`
+    ///
+    /// ```rust
+    /// # use saphir::prelude::*;
+    /// # use saphir::redirect::Redirect;
+    /// async fn legacy_profile(req: Request) -> Result<Redirect, SaphirError> {
+    ///     let id = req.captures().get("id").cloned().unwrap_or_default();
+    ///     let location = req.url_for("get_user", vec![("id", id)])?;
+    ///     Redirect::moved_permanently()
+    ///         .location(&location)
+    ///         .build()
+    ///         .map_err(|e| SaphirError::Other(format!("{:?}", e)))
+    /// }
+    /// ```
     #[inline]
     pub fn location(mut self, location: &str) -> Self {
         self.location = Some(location.to_string());
@@ -167,7 +182,8 @@ impl Builder {
         };
 
         if let Some(query) = self.query.take().transpose()? {
-            url.push('?');
+            // The location may already carry a query, e.g. from `url_for`
+            url.push(if url.contains('?') { '&' } else { '?' });
             url.push_str(query.as_str());
         }
 
@@ -1,6 +1,9 @@
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
 use std::{
     collections::HashMap,
-    net::SocketAddr,
+    fmt::{Display, Formatter},
+    net::{IpAddr, SocketAddr},
     ops::{Deref, DerefMut},
 };
 
@@ -11,14 +14,19 @@ use hyper::body::Bytes;
 use crate::{
     body::{Body, FromBytes},
     error::SaphirError,
+    forwarded::{self, ClientInfo, TrustedProxies},
 };
 
 #[cfg(feature = "operation")]
 use crate::http_context::operation::OperationId;
+#[cfg(feature = "https")]
+use crate::tls::TlsInfo;
 use crate::{
     prelude::{Cookie, CookieJar},
     responder::Responder,
+    router::Router,
 };
+use std::sync::Arc;
 
 pub trait FromRequest: Sized {
     type Err: Responder;
@@ -27,6 +35,93 @@ pub trait FromRequest: Sized {
     fn from_request(req: &mut Request) -> Self::Fut;
 }
 
+/// Address of the peer which sent a request
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PeerAddr {
+    /// Peer connected through a tcp socket
+    Tcp(SocketAddr),
+    /// Peer connected through a unix domain socket
+    #[cfg(unix)]
+    Unix(UnixPeerAddr),
+}
+
+impl PeerAddr {
+    /// Return the socket address of the peer if it is connected through tcp
+    #[inline]
+    pub fn socket_addr(&self) -> Option<&SocketAddr> {
+        match self {
+            PeerAddr::Tcp(addr) => Some(addr),
+            #[cfg(unix)]
+            PeerAddr::Unix(_) => None,
+        }
+    }
+
+    /// Return the ip address of the peer if it is connected through tcp
+    #[inline]
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.socket_addr().map(|addr| addr.ip())
+    }
+}
+
+impl From<SocketAddr> for PeerAddr {
+    fn from(addr: SocketAddr) -> Self {
+        PeerAddr::Tcp(addr)
+    }
+}
+
+impl Display for PeerAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PeerAddr::Tcp(addr) => Display::fmt(addr, f),
+            #[cfg(unix)]
+            PeerAddr::Unix(peer) => Display::fmt(peer, f),
+        }
+    }
+}
+
+/// Peer of a unix domain socket connection. Clients usually connect with an
+/// unnamed socket, so the credentials of the peer process are what identify
+/// it.
+#[cfg(unix)]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UnixPeerAddr {
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) uid: Option<u32>,
+    pub(crate) gid: Option<u32>,
+}
+
+#[cfg(unix)]
+impl UnixPeerAddr {
+    /// Path the peer socket is bound to, if any
+    #[inline]
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// User id of the peer process
+    #[inline]
+    pub fn uid(&self) -> Option<u32> {
+        self.uid
+    }
+
+    /// Group id of the peer process
+    #[inline]
+    pub fn gid(&self) -> Option<u32> {
+        self.gid
+    }
+}
+
+#[cfg(unix)]
+impl Display for UnixPeerAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (&self.path, self.uid) {
+            (Some(path), _) => write!(f, "unix:{}", path.display()),
+            (None, Some(uid)) => write!(f, "unix:(uid {})", uid),
+            (None, None) => f.write_str("unix:(unnamed)"),
+        }
+    }
+}
+
 /// Struct that wraps a hyper request + some magic
 pub struct Request<T = Body<Bytes>> {
     #[doc(hidden)]
@@ -36,7 +131,14 @@ pub struct Request<T = Body<Bytes>> {
     #[doc(hidden)]
     cookies: CookieJar,
     #[doc(hidden)]
-    peer_addr: Option<SocketAddr>,
+    peer_addr: Option<PeerAddr>,
+    #[doc(hidden)]
+    trusted_proxies: Option<Arc<TrustedProxies>>,
+    #[doc(hidden)]
+    router: Option<Router>,
+    #[doc(hidden)]
+    #[cfg(feature = "https")]
+    tls_info: Option<Arc<TlsInfo>>,
     #[doc(hidden)]
     #[cfg(feature = "operation")]
     operation_id: OperationId,
@@ -44,24 +146,149 @@ pub struct Request<T = Body<Bytes>> {
 
 impl<T> Request<T> {
     #[doc(hidden)]
-    pub fn new(raw: RawRequest<T>, peer_addr: Option<SocketAddr>) -> Self {
+    pub fn new(raw: RawRequest<T>, peer_addr: Option<PeerAddr>) -> Self {
         Request {
             inner: raw,
             captures: Default::default(),
             cookies: Default::default(),
             peer_addr,
+            trusted_proxies: None,
+            router: None,
+            #[cfg(feature = "https")]
+            tls_info: None,
             #[cfg(feature = "operation")]
             operation_id: OperationId::default(),
         }
     }
 
-    /// Return the Peer SocketAddr if one was available when receiving the
-    /// request
+    #[cfg(feature = "https")]
+    pub(crate) fn set_tls_info(&mut self, tls_info: Option<Arc<TlsInfo>>) {
+        self.tls_info = tls_info;
+    }
+
+    pub(crate) fn set_trusted_proxies(&mut self, trusted_proxies: Option<Arc<TrustedProxies>>) {
+        self.trusted_proxies = trusted_proxies;
+    }
+
+    pub(crate) fn set_router(&mut self, router: Option<Router>) {
+        self.router = router;
+    }
+
+    /// Return the socket address of the peer if one was available when
+    /// receiving the request. Peers connected through a unix domain socket
+    /// have none, see `peer`
     #[inline]
     pub fn peer_addr(&self) -> Option<&SocketAddr> {
+        self.peer_addr.as_ref().and_then(PeerAddr::socket_addr)
+    }
+
+    /// Return the address of the peer, tcp or unix, if one was available when
+    /// receiving the request
+    #[inline]
+    pub fn peer(&self) -> Option<&PeerAddr> {
         self.peer_addr.as_ref()
     }
 
+    fn client_info(&self) -> ClientInfo<'_> {
+        #[cfg(feature = "https")]
+        let scheme = if self.tls_info.is_some() { "https" } else { "http" };
+        #[cfg(not(feature = "https"))]
+        let scheme = "http";
+        forwarded::resolve(
+            self.inner.headers(),
+            self.inner.uri(),
+            self.peer_addr.as_ref(),
+            scheme,
+            self.trusted_proxies.as_deref(),
+        )
+    }
+
+    /// Build the url of the route of the handler named `name`, see
+    /// [`Router::url_for`](../router/struct.Router.html#method.url_for).
+    ///
+    /// ```rust
+    /// # use saphir::prelude::*;
+    /// async fn create_user(req: Request) -> Result<impl Responder, SaphirError> {
+    ///     let location = req.url_for("get_user", vec![("id", 42)])?;
+    ///     Ok((201, location))
+    /// }
+    /// ```
+    pub fn url_for<I, K, V>(&self, name: &str, params: I) -> Result<String, SaphirError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: ToString,
+    {
+        self.router
+            .as_ref()
+            .ok_or_else(|| SaphirError::Other("The request was not received by a router".to_string()))?
+            .url_for(name, params)
+    }
+
+    /// Return the ip address of the client which originated the request.
+    ///
+    /// When the peer is a trusted proxy of the listener, the `Forwarded` or
+    /// `X-Forwarded-For` headers are walked from right to left until reaching
+    /// an address which is not a trusted proxy. `None` if the client is
+    /// unknown.
+    pub fn client_addr(&self) -> Option<IpAddr> {
+        self.client_info().addr
+    }
+
+    /// Return the scheme used by the client which originated the request,
+    /// reported by trusted proxies like `client_addr`
+    pub fn client_scheme(&self) -> &str {
+        self.client_info().scheme
+    }
+
+    /// Return the host requested by the client which originated the request,
+    /// reported by trusted proxies like `client_addr` or from the `Host`
+    /// header
+    pub fn client_host(&self) -> Option<&str> {
+        self.client_info().host
+    }
+
+    /// Return true if the client waits for a `100 Continue` interim response
+    /// before sending the body, through the `Expect: 100-continue` header.
+    ///
+    /// The `100 Continue` is sent by the server the first time the body is
+    /// polled. A guard or middleware answering before the body is read, e.g.
+    /// with a `401` or a `413`, sends its final response instead and the
+    /// client never streams the body. The same applies when the handler never
+    /// reads the body: the client receives the response directly and the
+    /// connection is closed afterward, since the unread body may still be on
+    /// its way. See [`ExpectContinue`](../guard/struct.ExpectContinue.html) to
+    /// check these requests from their headers.
+    pub fn expects_continue(&self) -> bool {
+        self.inner.version() == http::Version::HTTP_11
+            && self
+                .inner
+                .headers()
+                .get(http::header::EXPECT)
+                .map(|e| e.as_bytes().eq_ignore_ascii_case(b"100-continue"))
+                .unwrap_or(false)
+    }
+
+    /// Using Feature `https`
+    ///
+    /// Return the information of the tls session the request was received on,
+    /// `None` if the request was not received over tls
+    #[inline]
+    #[cfg(feature = "https")]
+    pub fn tls_info(&self) -> Option<&TlsInfo> {
+        self.tls_info.as_deref()
+    }
+
+    /// Using Feature `https`
+    ///
+    /// Return the hostname requested by the client through server name
+    /// indication (SNI) during the tls handshake
+    #[inline]
+    #[cfg(feature = "https")]
+    pub fn sni_hostname(&self) -> Option<&str> {
+        self.tls_info.as_ref().and_then(|tls| tls.sni_hostname())
+    }
+
     /// Return the OperationId of the request
     #[inline]
     #[cfg(feature = "operation")]
@@ -79,7 +306,12 @@ impl<T> Request<T> {
     ///
     #[inline]
     pub fn peer_addr_mut(&mut self) -> Option<&mut SocketAddr> {
-        self.peer_addr.as_mut()
+        match self.peer_addr.as_mut() {
+            Some(PeerAddr::Tcp(addr)) => Some(addr),
+            #[cfg(unix)]
+            Some(PeerAddr::Unix(_)) => None,
+            None => None,
+        }
     }
 
     /// Get the cookies sent by the browsers.
@@ -167,6 +399,10 @@ impl<T> Request<T> {
             captures,
             cookies,
             peer_addr,
+            trusted_proxies,
+            router,
+            #[cfg(feature = "https")]
+            tls_info,
             #[cfg(feature = "operation")]
             operation_id,
         } = self;
@@ -176,6 +412,10 @@ impl<T> Request<T> {
             captures,
             cookies,
             peer_addr,
+            trusted_proxies,
+            router,
+            #[cfg(feature = "https")]
+            tls_info,
             #[cfg(feature = "operation")]
             operation_id,
         }
@@ -201,6 +441,10 @@ impl<T> Request<T> {
             captures,
             cookies,
             peer_addr,
+            trusted_proxies,
+            router,
+            #[cfg(feature = "https")]
+            tls_info,
             #[cfg(feature = "operation")]
             operation_id,
         } = self;
@@ -213,6 +457,10 @@ impl<T> Request<T> {
             captures,
             cookies,
             peer_addr,
+            trusted_proxies,
+            router,
+            #[cfg(feature = "https")]
+            tls_info,
             #[cfg(feature = "operation")]
             operation_id,
         }
@@ -270,6 +518,10 @@ impl<T: FromBytes + Unpin + 'static> Request<Body<T>> {
             captures,
             cookies,
             peer_addr,
+            trusted_proxies,
+            router,
+            #[cfg(feature = "https")]
+            tls_info,
             #[cfg(feature = "operation")]
             operation_id,
         } = self;
@@ -284,6 +536,10 @@ impl<T: FromBytes + Unpin + 'static> Request<Body<T>> {
             captures,
             cookies,
             peer_addr,
+            trusted_proxies,
+            router,
+            #[cfg(feature = "https")]
+            tls_info,
             #[cfg(feature = "operation")]
             operation_id,
         })
@@ -308,6 +564,10 @@ impl<T, E> Request<Result<T, E>> {
             captures,
             cookies,
             peer_addr,
+            trusted_proxies,
+            router,
+            #[cfg(feature = "https")]
+            tls_info,
             #[cfg(feature = "operation")]
             operation_id,
         } = self;
@@ -318,6 +578,10 @@ impl<T, E> Request<Result<T, E>> {
             captures,
             cookies,
             peer_addr,
+            trusted_proxies,
+            router,
+            #[cfg(feature = "https")]
+            tls_info,
             #[cfg(feature = "operation")]
             operation_id,
         })
@@ -341,6 +605,10 @@ impl<T> Request<Option<T>> {
             captures,
             cookies,
             peer_addr,
+            trusted_proxies,
+            router,
+            #[cfg(feature = "https")]
+            tls_info,
             #[cfg(feature = "operation")]
             operation_id,
         } = self;
@@ -351,6 +619,10 @@ impl<T> Request<Option<T>> {
             captures,
             cookies,
             peer_addr,
+            trusted_proxies,
+            router,
+            #[cfg(feature = "https")]
+            tls_info,
             #[cfg(feature = "operation")]
             operation_id,
         })
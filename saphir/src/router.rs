@@ -16,17 +16,28 @@ use crate::{
     guard::{Builder as GuardBuilder, GuardChain, GuardChainEnd},
     handler::DynHandler,
     http_context::{HandlerMetadata, HttpContext, RouteId, State},
+    middleware::{Builder as MiddlewareBuilder, MiddleChainEnd, MiddlewareChain},
     request::Request,
     responder::{DynResponder, Responder},
-    utils::{EndpointResolver, EndpointResolverResult},
+    utils::{EndpointResolver, EndpointResolverResult, RouteTree, UriPathSegmentMatcher},
 };
 use futures::{future::BoxFuture, FutureExt};
 use http::Method;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 /// Builder type for the router
 pub struct Builder<Chain: RouterChain + Send + Unpin + 'static + Sync> {
-    resolver: HashMap<String, EndpointResolver>,
+    resolver: HashMap<(Option<String>, String), EndpointResolver>,
+    routes: Vec<RouteInfo>,
+    prefix: String,
+    host: Option<String>,
+    endpoints: HashSet<(u64, Method)>,
+    guards: Box<dyn GuardChain>,
+    middlewares: Option<Arc<dyn MiddlewareChain>>,
+    scopes: HashMap<(u64, Method), Vec<Arc<dyn MiddlewareChain>>>,
     chain: Chain,
 }
 
@@ -34,6 +45,13 @@ impl Default for Builder<RouterChainEnd> {
     fn default() -> Self {
         Self {
             resolver: Default::default(),
+            routes: Vec::new(),
+            prefix: String::new(),
+            host: None,
+            endpoints: Default::default(),
+            guards: GuardBuilder::default().build(),
+            middlewares: None,
+            scopes: Default::default(),
             chain: RouterChainEnd { handlers: Default::default() },
         }
     }
@@ -59,18 +77,10 @@ impl<Controllers: 'static + RouterChain + Unpin + Send + Sync> Builder<Controlle
     where
         H: 'static + DynHandler<Body> + Send + Sync,
     {
-        let endpoint_id = if let Some(er) = self.resolver.get_mut(route) {
-            er.add_method(method.clone());
-            er.id()
-        } else {
-            let er = EndpointResolver::new(route, method.clone()).expect("Unable to construct endpoint resolver");
-            let er_id = er.id();
-            self.resolver.insert(route.to_string(), er);
-            er_id
-        };
+        let guards = crate::guard::Builder::default().build();
+        let endpoint_id = self.add_endpoint(route.to_string(), method.clone(), None, None, guards.as_ref());
 
-        self.chain
-            .add_handler(endpoint_id, method, Box::new(handler), crate::guard::Builder::default().build());
+        self.chain.add_handler(endpoint_id, method, Box::new(handler), guards);
 
         self
     }
@@ -104,18 +114,57 @@ impl<Controllers: 'static + RouterChain + Unpin + Send + Sync> Builder<Controlle
         F: FnOnce(GuardBuilder<GuardChainEnd>) -> GuardBuilder<Chain>,
         Chain: GuardChain + 'static,
     {
-        let endpoint_id = if let Some(er) = self.resolver.get_mut(route) {
-            er.add_method(method.clone());
-            er.id()
-        } else {
-            let er = EndpointResolver::new(route, method.clone()).expect("Unable to construct endpoint resolver");
-            let er_id = er.id();
-            self.resolver.insert(route.to_string(), er);
-            er_id
-        };
+        let guards = guards(GuardBuilder::default()).build();
+        let endpoint_id = self.add_endpoint(route.to_string(), method.clone(), None, None, guards.as_ref());
+
+        self.chain.add_handler(endpoint_id, method, Box::new(handler), guards);
+
+        self
+    }
+
+    /// Add a request handler to a given path, with options overriding the
+    /// defaults of the listener
+    ///
+    /// ```rust
+    /// # use saphir::router::Builder as RBuilder;
+    /// # use saphir::prelude::*;
+    /// #
+    /// # let builder = RBuilder::default();
+    /// async fn upload_handler(req: Request<Body>) -> impl Responder { 200 }
+    ///
+    /// // Uploads of up to 100MB, taking up to 5 minutes
+    /// builder.route_with_options(
+    ///     "/upload",
+    ///     Method::POST,
+    ///     upload_handler,
+    ///     RouteOptions::new().timeout(300_000).body_limit(100 * 1024 * 1024),
+    /// );
+    /// // ...
+    /// ```
+    pub fn route_with_options<H>(mut self, route: &str, method: Method, handler: H, options: RouteOptions) -> Self
+    where
+        H: 'static + DynHandler<Body> + Send + Sync,
+    {
+        let guards = crate::guard::Builder::default().build();
+        let endpoint_id = self.add_endpoint(route.to_string(), method.clone(), Some(options.metadata(None)), None, guards.as_ref());
+
+        self.chain.add_handler(endpoint_id, method, Box::new(handler), guards);
+
+        self
+    }
+
+    /// Add a request handler to a given path behind guards, with options
+    /// overriding the defaults of the listener
+    pub fn route_with_guards_and_options<H, F, Chain>(mut self, route: &str, method: Method, handler: H, guards: F, options: RouteOptions) -> Self
+    where
+        H: 'static + DynHandler<Body> + Send + Sync,
+        F: FnOnce(GuardBuilder<GuardChainEnd>) -> GuardBuilder<Chain>,
+        Chain: GuardChain + 'static,
+    {
+        let guards = guards(GuardBuilder::default()).build();
+        let endpoint_id = self.add_endpoint(route.to_string(), method.clone(), Some(options.metadata(None)), None, guards.as_ref());
 
-        self.chain
-            .add_handler(endpoint_id, method, Box::new(handler), guards(GuardBuilder::default()).build());
+        self.chain.add_handler(endpoint_id, method, Box::new(handler), guards);
 
         self
     }
@@ -140,27 +189,28 @@ impl<Controllers: 'static + RouterChain + Unpin + Send + Sync> Builder<Controlle
     /// ```
     pub fn controller<C: Controller + Send + Unpin + Sync>(mut self, controller: C) -> Builder<RouterChainLink<C, Controllers>> {
         let mut handlers = HashMap::new();
-        for (name, method, subroute, handler, guard_chain) in controller.handlers() {
+        for (name, method, subroute, handler, guard_chain, options) in controller.handlers() {
             let route = format!("{}{}", C::BASE_PATH, subroute);
-            let meta = name.map(|name| HandlerMetadata {
-                route_id: Default::default(),
-                name: Some(name),
-            });
-            let endpoint_id = if let Some(er) = self.resolver.get_mut(&route) {
-                er.add_method_with_metadata(method.clone(), meta);
-                er.id()
-            } else {
-                let er = EndpointResolver::new_with_metadata(&route, method.clone(), meta).expect("Unable to construct endpoint resolver");
-                let er_id = er.id();
-                self.resolver.insert(route, er);
-                er_id
-            };
+            let endpoint_id = self.add_endpoint(
+                route,
+                method.clone(),
+                Some(options.metadata(name)),
+                Some(std::any::type_name::<C>()),
+                guard_chain.as_ref(),
+            );
 
             handlers.insert((endpoint_id, method), (handler, guard_chain));
         }
 
         Builder {
             resolver: self.resolver,
+            routes: self.routes,
+            prefix: self.prefix,
+            host: self.host,
+            endpoints: self.endpoints,
+            guards: self.guards,
+            middlewares: self.middlewares,
+            scopes: self.scopes,
             chain: RouterChainLink {
                 controller,
                 handlers,
@@ -169,23 +219,456 @@ impl<Controllers: 'static + RouterChain + Unpin + Send + Sync> Builder<Controlle
         }
     }
 
-    pub(crate) fn build(self) -> Router {
-        let Builder { resolver, chain: controllers } = self;
+    /// Mount a sub-router under `prefix`. Routes and controllers of the
+    /// sub-router have their path prefixed, and its guards and middlewares
+    /// only apply to its own routes, after the ones of the parent.
+    ///
+    /// ```rust
+    /// # use saphir::router::Builder as RBuilder;
+    /// # use saphir::prelude::*;
+    /// #
+    /// # let builder = RBuilder::default();
+    /// async fn list_users(req: Request<Body>) -> impl Responder { 200 }
+    ///
+    /// async fn admin_only(req: Request<Body>) -> Result<Request<Body>, u16> {
+    ///     if req.headers().contains_key(header::AUTHORIZATION) { Ok(req) } else { Err(401) }
+    /// }
+    ///
+    /// async fn audit(ctx: HttpContext, chain: &dyn MiddlewareChain) -> Result<HttpContext, SaphirError> {
+    ///     // Log admin requests
+    ///     chain.next(ctx).await
+    /// }
+    ///
+    /// // GET /admin/users, only for admins
+    /// builder.scope("/admin", |r| {
+    ///     r.guards(|g| g.apply(admin_only))
+    ///         .middlewares(|m| m.apply(audit, vec!["/"], None))
+    ///         .route("/users", Method::GET, list_users)
+    /// });
+    /// // ...
+    /// ```
+    pub fn scope<F, Chain>(self, prefix: &str, scope: F) -> Builder<RouterChainScope<Chain, Controllers>>
+    where
+        F: FnOnce(Builder<RouterChainEnd>) -> Builder<Chain>,
+        Chain: RouterChain + Unpin + Send + Sync + 'static,
+    {
+        self.mount(prefix, None, scope)
+    }
 
-        let mut resolvers: Vec<_> = resolver.into_iter().map(|(_, e)| e).collect();
-        resolvers.sort_unstable();
+    /// Mount a sub-router only serving the requests whose host matches
+    /// `pattern`. The pattern is either exact (`api.example.com`), a wildcard
+    /// label (`*.example.com`) or a captured label (`{tenant}.example.com`),
+    /// available in `Request::captures`. Routes constrained by a host take
+    /// precedence over the other ones, whatever their path.
+    ///
+    /// ```rust
+    /// # use saphir::router::Builder as RBuilder;
+    /// # use saphir::prelude::*;
+    /// #
+    /// # let builder = RBuilder::default();
+    /// async fn dashboard(req: Request<Body>) -> impl Responder {
+    ///     // Tenant of the request, e.g. acme for acme.example.com
+    ///     let tenant = req.captures().get("tenant").cloned();
+    ///     200
+    /// }
+    ///
+    /// async fn users(req: Request<Body>) -> impl Responder { 200 }
+    ///
+    /// builder
+    ///     .host("{tenant}.example.com", |r| r.route("/", Method::GET, dashboard))
+    ///     .host("admin.example.com", |r| r.scope("/users", |r| r.route("/", Method::GET, users)));
+    /// // ...
+    /// ```
+    pub fn host<F, Chain>(self, pattern: &str, routes: F) -> Builder<RouterChainScope<Chain, Controllers>>
+    where
+        F: FnOnce(Builder<RouterChainEnd>) -> Builder<Chain>,
+        Chain: RouterChain + Unpin + Send + Sync + 'static,
+    {
+        self.mount("", Some(pattern), routes)
+    }
+
+    fn mount<F, Chain>(self, prefix: &str, host: Option<&str>, scope: F) -> Builder<RouterChainScope<Chain, Controllers>>
+    where
+        F: FnOnce(Builder<RouterChainEnd>) -> Builder<Chain>,
+        Chain: RouterChain + Unpin + Send + Sync + 'static,
+    {
+        let Builder {
+            resolver,
+            routes,
+            prefix: parent_prefix,
+            host: parent_host,
+            mut endpoints,
+            guards,
+            middlewares,
+            scopes,
+            chain,
+        } = self;
+
+        let first_route = routes.len();
+        let mut sub = scope(Builder {
+            resolver,
+            routes,
+            prefix: format!("{}{}", parent_prefix, prefix.trim_end_matches('/')),
+            host: host.map(str::to_string).or_else(|| parent_host.clone()),
+            endpoints: Default::default(),
+            guards: GuardBuilder::default().build(),
+            middlewares: None,
+            scopes,
+            chain: RouterChainEnd { handlers: Default::default() },
+        });
+        sub.seal(first_route);
+
+        endpoints.extend(sub.endpoints.iter().cloned());
+        Builder {
+            resolver: sub.resolver,
+            routes: sub.routes,
+            prefix: parent_prefix,
+            host: parent_host,
+            endpoints,
+            guards,
+            middlewares,
+            scopes: sub.scopes,
+            chain: RouterChainScope {
+                endpoints: sub.endpoints,
+                guards: sub.guards,
+                scope: sub.chain,
+                rest: chain,
+            },
+        }
+    }
+
+    /// Apply guards to every route of the router, before the guards of the
+    /// route itself. Mostly useful to protect the routes of a `scope`.
+    pub fn guards<F, Chain>(mut self, guards: F) -> Self
+    where
+        F: FnOnce(GuardBuilder<GuardChainEnd>) -> GuardBuilder<Chain>,
+        Chain: GuardChain + 'static,
+    {
+        self.guards = guards(GuardBuilder::default()).build();
+        self
+    }
+
+    /// Apply middlewares to every route of the router, run after the
+    /// middlewares of the server and of the parent scopes, right before the
+    /// guards. Mostly useful to process the requests of a `scope` only.
+    pub fn middlewares<F, Chain>(mut self, middlewares: F) -> Self
+    where
+        F: FnOnce(MiddlewareBuilder<MiddleChainEnd>) -> MiddlewareBuilder<Chain>,
+        Chain: MiddlewareChain + 'static,
+    {
+        self.middlewares = Some(middlewares(MiddlewareBuilder::default()).build().into());
+        self
+    }
+
+    /// Return the routes registered so far, in their registration order
+    pub fn routes(&self) -> impl Iterator<Item = &RouteInfo> {
+        self.routes.iter()
+    }
+
+    fn add_endpoint(&mut self, route: String, method: Method, meta: Option<HandlerMetadata>, controller: Option<&'static str>, guards: &dyn GuardChain) -> u64 {
+        let route = match route.as_str() {
+            "/" if !self.prefix.is_empty() => self.prefix.clone(),
+            _ => format!("{}{}", self.prefix, route),
+        };
+
+        self.routes.push(RouteInfo {
+            host: self.host.clone(),
+            path: route.clone(),
+            method: method.clone(),
+            name: meta.as_ref().and_then(|meta| meta.name),
+            controller,
+            guards: guards.count(),
+        });
+
+        let key = (self.host.clone(), route);
+        let er_id = if let Some(er) = self.resolver.get_mut(&key) {
+            er.add_method_with_metadata(method.clone(), meta);
+            er.id()
+        } else {
+            let mut er = EndpointResolver::new_with_metadata(&key.1, method.clone(), meta).expect("Unable to construct endpoint resolver");
+            if let Some(host) = &key.0 {
+                er = er.with_host(host).expect("Unable to construct endpoint resolver");
+            }
+            let er_id = er.id();
+            self.resolver.insert(key, er);
+            er_id
+        };
+        self.endpoints.insert((er_id, method));
+        er_id
+    }
+
+    /// Apply the guards and middlewares of the router to the routes
+    /// registered from `first_route`
+    fn seal(&mut self, first_route: usize) {
+        let guards = self.guards.count();
+        for route in &mut self.routes[first_route..] {
+            route.guards += guards;
+        }
+
+        if let Some(middlewares) = self.middlewares.take() {
+            for endpoint in &self.endpoints {
+                self.scopes.entry(endpoint.clone()).or_default().insert(0, middlewares.clone());
+            }
+        }
+    }
+
+    pub(crate) fn build(mut self) -> Router {
+        self.seal(0);
+        let Builder {
+            resolver,
+            routes,
+            endpoints,
+            guards,
+            scopes,
+            chain: controllers,
+            ..
+        } = self;
+
+        let chain: Box<dyn RouterChain + Send + Unpin + Sync> = if guards.is_end() {
+            Box::new(controllers)
+        } else {
+            Box::new(RouterChainScope {
+                endpoints,
+                guards,
+                scope: controllers,
+                rest: RouterChainEnd { handlers: Default::default() },
+            })
+        };
 
         Router {
             inner: Arc::new(RouterInner {
-                resolvers,
-                chain: Box::new(controllers),
+                resolvers: RouteTree::new(resolver.into_iter().map(|(_, e)| e).collect()),
+                routes,
+                scopes,
+                chain,
             }),
         }
     }
 }
 
+/// Options of a route, overriding the defaults of the listener
+///
+/// ```rust
+/// # use saphir::prelude::*;
+/// // Reports can take 2 minutes to generate but their requests stay small
+/// let options = RouteOptions::new().timeout(120_000).body_limit(16 * 1024);
+/// ```
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct RouteOptions {
+    name: Option<&'static str>,
+    timeout_ms: Option<Option<u64>>,
+    body_limit: Option<usize>,
+}
+
+impl RouteOptions {
+    /// Create options keeping every default of the listener
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name the handler of the route, making it usable by `Router::url_for`
+    #[inline]
+    pub fn name(mut self, name: &'static str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Set the request timeout of the route in milliseconds, see
+    /// `ListenerBuilder::request_timeout`
+    #[inline]
+    pub fn timeout<T: Into<Option<u64>>>(mut self, timeout_ms: T) -> Self {
+        self.timeout_ms = timeout_ms.into().map(Some);
+        self
+    }
+
+    /// Let the route run without any timeout, even if the listener has a
+    /// `request_timeout`
+    #[inline]
+    pub fn no_timeout(mut self) -> Self {
+        self.timeout_ms = Some(None);
+        self
+    }
+
+    /// Set the maximum number of bytes of the request body of the route, see
+    /// `ListenerBuilder::request_body_max_bytes`
+    #[inline]
+    pub fn body_limit<T: Into<Option<usize>>>(mut self, body_limit: T) -> Self {
+        self.body_limit = body_limit.into();
+        self
+    }
+
+    fn metadata(self, name: Option<&'static str>) -> HandlerMetadata {
+        HandlerMetadata {
+            route_id: Default::default(),
+            name: name.or(self.name),
+            route: None,
+            timeout_ms: self.timeout_ms,
+            body_limit: self.body_limit,
+        }
+    }
+}
+
+/// A handler registered in the router, for a single method of a route
+///
+/// ```rust
+/// # use saphir::prelude::*;
+/// # async fn user(_req: Request) -> u16 { 200 }
+/// let server = Server::builder().configure_router(|r| r.route("/users/<id>", Method::GET, user));
+/// for route in server.routes() {
+///     // GET /users/<id>
+///     println!("{}", route);
+/// }
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RouteInfo {
+    host: Option<String>,
+    path: String,
+    method: Method,
+    name: Option<&'static str>,
+    controller: Option<&'static str>,
+    guards: usize,
+}
+
+impl RouteInfo {
+    /// Host pattern the route is constrained to, if any
+    #[inline]
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    /// Path template of the route, e.g. `/users/<id>`
+    #[inline]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Method of the handler, `ANY` for handlers accepting every method
+    #[inline]
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Name of the handler, as found in its `HandlerMetadata`
+    #[inline]
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// Type name of the controller of the handler, if any
+    #[inline]
+    pub fn controller(&self) -> Option<&'static str> {
+        self.controller
+    }
+
+    /// Number of guards run before the handler
+    #[inline]
+    pub fn guards(&self) -> usize {
+        self.guards
+    }
+
+    /// Return true if the handler is named `name`, optionally qualified with
+    /// the name of its controller
+    fn is_named(&self, name: &str) -> bool {
+        let handler = match self.name {
+            Some(handler) => handler,
+            None => return false,
+        };
+        let controller = self.controller.and_then(|c| c.rsplit("::").next());
+        handler == name || (controller.is_some() && name.strip_suffix(handler).and_then(|c| c.strip_suffix("::")) == controller)
+    }
+}
+
+impl std::fmt::Display for RouteInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}{}", self.method, self.host.as_deref().unwrap_or_default(), self.path)?;
+        match (self.controller, self.name) {
+            (Some(controller), Some(name)) => write!(f, " -> {}::{}", controller, name)?,
+            (Some(controller), None) => write!(f, " -> {}", controller)?,
+            (None, Some(name)) => write!(f, " -> {}", name)?,
+            (None, None) => {}
+        }
+        if self.guards > 0 {
+            write!(f, " ({} guard{})", self.guards, if self.guards > 1 { "s" } else { "" })?;
+        }
+        Ok(())
+    }
+}
+
+/// Substitute `params` into the path template `route`, appending the unused
+/// ones as the query string
+fn build_url(route: &str, mut params: Vec<(String, String)>) -> Result<String, SaphirError> {
+    let mut take = |name: &str| {
+        params
+            .iter()
+            .position(|(k, _)| k == name)
+            .map(|i| params.remove(i).1)
+            .ok_or_else(|| SaphirError::MissingParameter(name.to_string(), false))
+    };
+
+    let mut url = String::with_capacity(route.len());
+    for segment in route.split('/').filter(|s| !s.is_empty()) {
+        url.push('/');
+        if segment.contains("**") || segment.contains("..") {
+            let name = segment.trim_start_matches("**").trim_start_matches("..");
+            if name.is_empty() {
+                return Err(SaphirError::Other(format!("Unable to build the url of {}, its wildcard is not named", route)));
+            }
+            let value = take(name)?;
+            for (i, part) in value.trim_start_matches('/').split('/').enumerate() {
+                if i > 0 {
+                    url.push('/');
+                }
+                percent_encode(part, &mut url);
+            }
+            continue;
+        }
+
+        match UriPathSegmentMatcher::new(segment).map_err(SaphirError::Other)? {
+            UriPathSegmentMatcher::Static { .. } => url.push_str(segment),
+            UriPathSegmentMatcher::Wildcard { .. } => {
+                return Err(SaphirError::Other(format!("Unable to build the url of {}, its wildcard is not named", route)));
+            }
+            matcher => {
+                let name = segment[1..segment.len() - 1].split("#r").next().unwrap_or_default();
+                let value = take(name)?;
+                if !matcher.matches(&value) {
+                    return Err(SaphirError::InvalidParameter(name.to_string(), false));
+                }
+                percent_encode(&value, &mut url);
+            }
+        }
+    }
+
+    if url.is_empty() || (route.ends_with('/') && !url.ends_with('/')) {
+        url.push('/');
+    }
+
+    for (i, (key, value)) in params.iter().enumerate() {
+        url.push(if i == 0 { '?' } else { '&' });
+        percent_encode(key, &mut url);
+        url.push('=');
+        percent_encode(value, &mut url);
+    }
+
+    Ok(url)
+}
+
+/// Percent-encode every byte of `value` but the unreserved characters of
+/// RFC 3986
+fn percent_encode(value: &str, out: &mut String) {
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => out.push(b as char),
+            b => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+}
+
 struct RouterInner {
-    resolvers: Vec<EndpointResolver>,
+    resolvers: RouteTree,
+    routes: Vec<RouteInfo>,
+    scopes: HashMap<(u64, Method), Vec<Arc<dyn MiddlewareChain>>>,
     chain: Box<dyn RouterChain + Send + Unpin + Sync>,
 }
 
@@ -200,46 +683,92 @@ impl Router {
         Builder::default()
     }
 
-    pub fn resolve(&self, req: &mut Request<Body>) -> Result<u64, u16> {
-        let mut method_not_allowed = false;
-        for endpoint_resolver in &self.inner.resolvers {
-            match endpoint_resolver.resolve(req) {
-                EndpointResolverResult::InvalidPath => continue,
-                EndpointResolverResult::MethodNotAllowed => method_not_allowed = true,
-                EndpointResolverResult::Match(_) => return Ok(endpoint_resolver.id()),
-            }
-        }
+    /// Return the registered routes, in their registration order
+    pub fn routes(&self) -> impl Iterator<Item = &RouteInfo> {
+        self.inner.routes.iter()
+    }
 
-        if method_not_allowed {
-            Err(405)
-        } else {
-            Err(404)
+    /// Build the url of the route of the handler named `name`, the reverse of
+    /// routing.
+    ///
+    /// `name` is the name of the handler, qualified with the name of its
+    /// controller (e.g. `UserController::get_user`) when several controllers
+    /// use it. Parameters named after a path variable are substituted into
+    /// the route template, values of custom regex segments are validated
+    /// against their regex, and the remaining parameters are appended as
+    /// the query string. Every value is percent-encoded.
+    ///
+    /// The url is only a path and a query: the host pattern of a route
+    /// mounted with `Builder::host` is not part of it, it is up to the caller
+    /// to send the url to a matching host.
+    ///
+    /// ```rust
+    /// # use saphir::prelude::*;
+    /// async fn get_user(req: Request) -> impl Responder { 200 }
+    ///
+    /// async fn create_user(req: Request) -> Result<String, SaphirError> {
+    ///     // /users/42?tab=recent%20posts
+    ///     req.url_for("get_user", vec![("id", "42"), ("tab", "recent posts")])
+    /// }
+    ///
+    /// let server = Server::builder().configure_router(|r| {
+    ///     r.route_with_options("/users/<id#r([0-9]+)>", Method::GET, get_user, RouteOptions::new().name("get_user"))
+    ///         .route("/users", Method::POST, create_user)
+    /// });
+    /// ```
+    pub fn url_for<I, K, V>(&self, name: &str, params: I) -> Result<String, SaphirError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: ToString,
+    {
+        let mut candidates = self.inner.routes.iter().filter(|r| r.is_named(name));
+
+        let route = candidates.next().ok_or_else(|| SaphirError::Other(format!("No route is named {}", name)))?;
+        if candidates.any(|r| r.path != route.path) {
+            return Err(SaphirError::Other(format!("Route name {} is ambiguous, qualify it with its controller", name)));
         }
-    }
 
-    pub fn resolve_metadata(&self, req: &mut Request) -> HandlerMetadata {
-        let mut method_not_allowed = false;
+        build_url(&route.path, params.into_iter().map(|(k, v)| (k.as_ref().to_string(), v.to_string())).collect())
+    }
 
-        for endpoint_resolver in &self.inner.resolvers {
-            match endpoint_resolver.resolve(req) {
-                EndpointResolverResult::InvalidPath => continue,
-                EndpointResolverResult::MethodNotAllowed => method_not_allowed = true,
-                EndpointResolverResult::Match(meta) => return meta.clone(),
-            }
+    pub fn resolve(&self, req: &mut Request<Body>) -> Result<u64, u16> {
+        match self.resolve_metadata(req).route_id {
+            RouteId::Id(id) => Ok(id),
+            RouteId::Error(e) => Err(e),
         }
+    }
 
-        if method_not_allowed {
-            HandlerMetadata::not_allowed()
-        } else {
-            HandlerMetadata::not_found()
+    /// Resolve the handler of `req` with a single lookup in the route tree,
+    /// capturing its path variables
+    pub fn resolve_metadata(&self, req: &mut Request) -> HandlerMetadata {
+        match self.inner.resolvers.resolve(req) {
+            EndpointResolverResult::Match(meta) => meta.clone(),
+            EndpointResolverResult::MethodNotAllowed => HandlerMetadata::not_allowed(),
+            EndpointResolverResult::InvalidPath => HandlerMetadata::not_found(),
         }
     }
 
     pub async fn dispatch(&self, mut ctx: HttpContext) -> Result<HttpContext, SaphirError> {
-        let req = ctx.state.take_request().ok_or(SaphirError::RequestMovedBeforeHandler)?;
         // # SAFETY #
-        // The router is initialized in static memory when calling run on Server.
+        // The router is leaked into static memory when building the Server.
         let static_self = unsafe { std::mem::transmute::<&'_ Self, &'static Self>(self) };
+
+        // Run the middlewares of the scopes of the route, outermost first, each
+        // of them ending by dispatching the context here again
+        if let RouteId::Id(id) = ctx.metadata.route_id {
+            let scope = ctx
+                .state
+                .request()
+                .and_then(|req| static_self.inner.scopes.get(&(id, req.method().clone())))
+                .and_then(|scopes| scopes.get(ctx.scope_depth));
+            if let Some(middlewares) = scope {
+                ctx.scope_depth += 1;
+                return middlewares.next(ctx).await;
+            }
+        }
+
+        let req = ctx.state.take_request().ok_or(SaphirError::RequestMovedBeforeHandler)?;
         let b = crate::response::Builder::new();
         let route_id = match ctx.metadata.route_id {
             RouteId::Id(id) => id,
@@ -301,6 +830,41 @@ impl RouterChain for RouterChainEnd {
     }
 }
 
+#[doc(hidden)]
+pub struct RouterChainScope<S, Rest: RouterChain> {
+    endpoints: HashSet<(u64, Method)>,
+    guards: Box<dyn GuardChain>,
+    scope: S,
+    rest: Rest,
+}
+
+impl<S: RouterChain + Sync + Send + 'static, Rest: RouterChain + Sync + Send> RouterChain for RouterChainScope<S, Rest> {
+    #[inline]
+    fn dispatch(&'static self, resolver_id: u64, req: Request<Body>) -> Option<BoxFuture<'static, Box<dyn DynResponder + Send>>> {
+        if !self.endpoints.contains(&(resolver_id, req.method().clone())) {
+            return self.rest.dispatch(resolver_id, req);
+        }
+
+        if self.guards.is_end() {
+            self.scope.dispatch(resolver_id, req)
+        } else {
+            let fut = self.guards.validate(req).then(move |req| async move {
+                match req.map(|req| self.scope.dispatch(resolver_id, req)) {
+                    Ok(Some(handler)) => handler.await,
+                    Ok(None) => Box::new(Some(404)) as Box<dyn DynResponder + Send>,
+                    Err(resp) => resp,
+                }
+            });
+            Some(fut.boxed())
+        }
+    }
+
+    #[inline]
+    fn add_handler(&mut self, endpoint_id: u64, method: Method, handler: Box<dyn DynHandler<Body> + Send + Sync>, guards: Box<dyn GuardChain>) {
+        self.rest.add_handler(endpoint_id, method, handler, guards);
+    }
+}
+
 #[doc(hidden)]
 pub struct RouterChainLink<C, Rest: RouterChain> {
     controller: C,
@@ -333,3 +897,195 @@ impl<C: Sync + Send, Rest: RouterChain + Sync + Send> RouterChain for RouterChai
         self.rest.add_handler(endpoint_id, method, handler, guards);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::SaphirError, http_context::HttpContext, server::Server, test_utils::TestClient};
+    use http::{header, HeaderValue};
+
+    async fn ok(_req: Request) -> u16 {
+        200
+    }
+
+    #[test]
+    fn route_table() {
+        use crate::controller::{Controller, ControllerEndpoint, EndpointsBuilder};
+
+        struct UserController;
+
+        impl UserController {
+            async fn get_user(&self, _req: Request) -> u16 {
+                200
+            }
+        }
+
+        impl Controller for UserController {
+            const BASE_PATH: &'static str = "/users";
+
+            fn handlers(&self) -> Vec<ControllerEndpoint<Self>> {
+                EndpointsBuilder::new()
+                    .add_with_guards_and_name("get_user", Method::GET, "/<id>", UserController::get_user, |g| {
+                        g.apply(|req: Request| async { Ok::<_, u16>(req) })
+                    })
+                    .build()
+            }
+        }
+
+        let builder = Server::builder().configure_router(|r| r.route("/ok", Method::POST, ok).controller(UserController));
+        let routes: Vec<_> = builder.routes().map(|r| r.to_string()).collect();
+        assert_eq!(
+            routes,
+            vec![
+                "POST /ok",
+                "GET /users/<id> -> saphir::router::tests::route_table::UserController::get_user (1 guard)"
+            ]
+        );
+
+        let route = builder.routes().nth(1).unwrap();
+        assert_eq!(route.path(), "/users/<id>");
+        assert_eq!(route.method(), Method::GET);
+        assert_eq!(route.name(), Some("get_user"));
+        assert_eq!(route.guards(), 1);
+
+        let client = TestClient::new(builder.configure_health(|h| h));
+        assert_eq!(client.handle().routes().count(), 6);
+    }
+
+    #[tokio::test]
+    async fn url_for() {
+        async fn links(req: Request) -> String {
+            let urls = vec![
+                req.url_for("get_user", vec![("id", "42")]),
+                req.url_for("get_user", vec![("id", "42"), ("q", "a b&c")]),
+                req.url_for("get_user", vec![("id", "me")]),
+                req.url_for("get_user", Vec::<(&str, &str)>::new()),
+                req.url_for("file", vec![("path", "docs/read me.md")]),
+                req.url_for("tag", vec![("tag", "é/x")]),
+                req.url_for("unknown", Vec::<(&str, &str)>::new()),
+            ];
+            urls.into_iter()
+                .map(|u| match u {
+                    Ok(url) => url,
+                    Err(SaphirError::MissingParameter(name, _)) => format!("missing {}", name),
+                    Err(SaphirError::InvalidParameter(name, _)) => format!("invalid {}", name),
+                    Err(_) => "error".to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+
+        let client = TestClient::new(Server::builder().configure_router(|r| {
+            r.route("/links", Method::GET, links)
+                .route_with_options("/users/<id#r([0-9]+)>/", Method::GET, ok, RouteOptions::new().name("get_user"))
+                .route_with_options("/files/**path", Method::GET, ok, RouteOptions::new().name("file"))
+                .route_with_options("/tags/{tag}", Method::GET, ok, RouteOptions::new().name("tag"))
+        }));
+        let res = client.get("/links").send().await.unwrap();
+        assert_eq!(
+            res.text(),
+            "/users/42/\n/users/42/?q=a%20b%26c\ninvalid id\nmissing id\n/files/docs/read%20me.md\n/tags/%C3%A9%2Fx\nerror"
+        );
+    }
+
+    #[tokio::test]
+    async fn scoped_routes() {
+        async fn admin_only(req: Request) -> Result<Request, u16> {
+            if req.headers().contains_key(header::AUTHORIZATION) {
+                Ok(req)
+            } else {
+                Err(401)
+            }
+        }
+
+        async fn tag(name: &'static str, ctx: HttpContext, chain: &dyn MiddlewareChain) -> Result<HttpContext, SaphirError> {
+            let mut ctx = chain.next(ctx).await?;
+            if let Some(res) = ctx.state.response_mut() {
+                res.headers_mut().append("x-scope", HeaderValue::from_static(name));
+            }
+            Ok(ctx)
+        }
+
+        async fn admin(ctx: HttpContext, chain: &dyn MiddlewareChain) -> Result<HttpContext, SaphirError> {
+            tag("admin", ctx, chain).await
+        }
+
+        async fn audit(ctx: HttpContext, chain: &dyn MiddlewareChain) -> Result<HttpContext, SaphirError> {
+            tag("audit", ctx, chain).await
+        }
+
+        let builder = Server::builder().configure_router(|r| {
+            r.route("/admin/users", Method::POST, ok).scope("/admin", |r| {
+                r.guards(|g| g.apply(admin_only))
+                    .middlewares(|m| m.apply(admin, vec!["/"], None))
+                    .route("/", Method::GET, ok)
+                    .route("/users", Method::GET, ok)
+                    .scope("/audit/", |r| {
+                        r.middlewares(|m| m.apply(audit, vec!["/"], None))
+                            .route_with_guards("/logs", Method::GET, ok, |g| g.apply(|req: Request| async { Ok::<_, u16>(req) }))
+                    })
+            })
+        });
+        let routes: Vec<_> = builder.routes().map(|r| r.to_string()).collect();
+        assert_eq!(
+            routes,
+            vec![
+                "POST /admin/users",
+                "GET /admin (1 guard)",
+                "GET /admin/users (1 guard)",
+                "GET /admin/audit/logs (2 guards)"
+            ]
+        );
+
+        let client = TestClient::new(builder);
+        client.get("/admin").send().await.unwrap().assert_status(401);
+        client.post("/admin/users").send().await.unwrap().assert_status(200);
+
+        let res = client.get("/admin/users").header("authorization", "admin").send().await.unwrap();
+        res.assert_status(200);
+        assert_eq!(res.headers().get_all("x-scope").iter().collect::<Vec<_>>(), vec!["admin"]);
+
+        let res = client.get("/admin/audit/logs").header("authorization", "admin").send().await.unwrap();
+        res.assert_status(200);
+        assert_eq!(res.headers().get_all("x-scope").iter().collect::<Vec<_>>(), vec!["audit", "admin"]);
+        client.get("/admin/audit/logs").send().await.unwrap().assert_status(401);
+    }
+
+    #[tokio::test]
+    async fn virtual_hosts() {
+        async fn host(req: Request) -> String {
+            format!("{} {:?}", req.uri().path(), req.captures().get("tenant"))
+        }
+
+        async fn fallback(_req: Request) -> &'static str {
+            "fallback"
+        }
+
+        let builder = Server::builder().configure_router(|r| {
+            r.route("/**", Method::GET, fallback)
+                .host("api.example.com", |r| r.route("/users", Method::GET, host))
+                .host("*.static.example.com", |r| r.route("/logo.png", Method::GET, host))
+                .host("{tenant}.example.com", |r| r.scope("/app", |r| r.route("/", Method::GET, host)))
+        });
+        let routes: Vec<_> = builder.routes().map(|r| r.to_string()).collect();
+        assert_eq!(
+            routes,
+            vec![
+                "GET /**",
+                "GET api.example.com/users",
+                "GET *.static.example.com/logo.png",
+                "GET {tenant}.example.com/app"
+            ]
+        );
+
+        let client = &TestClient::new(builder);
+        let get = |host: &'static str, path: &'static str| async move { client.get(path).header("host", host).send().await.unwrap().text() };
+        assert_eq!(get("API.example.com:8080", "/users").await, "/users None");
+        assert_eq!(get("cdn.static.example.com", "/logo.png").await, "/logo.png None");
+        assert_eq!(get("static.example.com", "/logo.png").await, "fallback");
+        assert_eq!(get("acme.example.com", "/app").await, "/app Some(\"acme\")");
+        assert_eq!(get("api.example.com", "/app").await, "/app Some(\"api\")");
+        assert_eq!(get("example.com", "/app").await, "fallback");
+        assert_eq!(get("api.example.com", "/missing").await, "fallback");
+    }
+}
@@ -0,0 +1,485 @@
+//! In-process client to test a saphir stack without opening sockets.
+//!
+//! ```rust
+//! use saphir::{prelude::*, test_utils::TestClient};
+//!
+//! async fn hello(_req: Request) -> (u16, &'static str) {
+//!     (200, "Hello")
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), SaphirError> {
+//!     let client = TestClient::new(Server::builder().configure_router(|r| r.route("/hello", Method::GET, hello)));
+//!
+//!     let res = client.get("/hello").send().await?;
+//!     res.assert_status(200);
+//!     assert_eq!(res.text(), "Hello");
+//!     Ok(())
+//! }
+//! ```
+use crate::{
+    body::Bytes,
+    cookie::{Cookie, CookieJar},
+    error::SaphirError,
+    middleware::MiddlewareChain,
+    router::RouterChain,
+    server::{Builder, ServerHandle},
+};
+use http::{
+    header::{HeaderName, HeaderValue, COOKIE, SET_COOKIE},
+    request::Builder as RawRequestBuilder,
+    HeaderMap, Method, Request as RawRequest, StatusCode,
+};
+use hyper::body::Body as RawBody;
+use parking_lot::Mutex;
+use std::{
+    convert::TryFrom,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "multipart")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Client dispatching requests directly into the stack of a server.
+///
+/// Cookies set by responses are kept and sent back with the following
+/// requests, like a browser would.
+pub struct TestClient {
+    handle: ServerHandle,
+    cookies: Mutex<CookieJar>,
+}
+
+impl TestClient {
+    /// Build the stack of the server, its listeners are never bound
+    pub fn new<Controllers, Middlewares>(builder: Builder<Controllers, Middlewares>) -> Self
+    where
+        Controllers: 'static + RouterChain + Unpin + Send + Sync,
+        Middlewares: 'static + MiddlewareChain + Unpin + Send + Sync,
+    {
+        TestClient {
+            handle: builder.build_handle(),
+            cookies: Mutex::new(CookieJar::new()),
+        }
+    }
+
+    /// Start a request with the given method and uri
+    pub fn request<U>(&self, method: Method, uri: U) -> TestRequestBuilder<'_>
+    where
+        http::Uri: TryFrom<U>,
+        <http::Uri as TryFrom<U>>::Error: Into<http::Error>,
+    {
+        TestRequestBuilder {
+            client: self,
+            inner: RawRequest::builder().method(method).uri(uri),
+            body: RawBody::empty(),
+            error: None,
+        }
+    }
+
+    /// Start a GET request
+    pub fn get<U>(&self, uri: U) -> TestRequestBuilder<'_>
+    where
+        http::Uri: TryFrom<U>,
+        <http::Uri as TryFrom<U>>::Error: Into<http::Error>,
+    {
+        self.request(Method::GET, uri)
+    }
+
+    /// Start a POST request
+    pub fn post<U>(&self, uri: U) -> TestRequestBuilder<'_>
+    where
+        http::Uri: TryFrom<U>,
+        <http::Uri as TryFrom<U>>::Error: Into<http::Error>,
+    {
+        self.request(Method::POST, uri)
+    }
+
+    /// Start a PUT request
+    pub fn put<U>(&self, uri: U) -> TestRequestBuilder<'_>
+    where
+        http::Uri: TryFrom<U>,
+        <http::Uri as TryFrom<U>>::Error: Into<http::Error>,
+    {
+        self.request(Method::PUT, uri)
+    }
+
+    /// Start a PATCH request
+    pub fn patch<U>(&self, uri: U) -> TestRequestBuilder<'_>
+    where
+        http::Uri: TryFrom<U>,
+        <http::Uri as TryFrom<U>>::Error: Into<http::Error>,
+    {
+        self.request(Method::PATCH, uri)
+    }
+
+    /// Start a DELETE request
+    pub fn delete<U>(&self, uri: U) -> TestRequestBuilder<'_>
+    where
+        http::Uri: TryFrom<U>,
+        <http::Uri as TryFrom<U>>::Error: Into<http::Error>,
+    {
+        self.request(Method::DELETE, uri)
+    }
+
+    /// Start a HEAD request
+    pub fn head<U>(&self, uri: U) -> TestRequestBuilder<'_>
+    where
+        http::Uri: TryFrom<U>,
+        <http::Uri as TryFrom<U>>::Error: Into<http::Error>,
+    {
+        self.request(Method::HEAD, uri)
+    }
+
+    /// Start a OPTIONS request
+    pub fn options<U>(&self, uri: U) -> TestRequestBuilder<'_>
+    where
+        http::Uri: TryFrom<U>,
+        <http::Uri as TryFrom<U>>::Error: Into<http::Error>,
+    {
+        self.request(Method::OPTIONS, uri)
+    }
+
+    /// Cookies currently kept by the client
+    pub fn cookies(&self) -> CookieJar {
+        self.cookies.lock().clone()
+    }
+
+    /// Forget every cookie kept by the client
+    pub fn clear_cookies(&self) {
+        *self.cookies.lock() = CookieJar::new();
+    }
+
+    /// Handle to the underlying server stack
+    pub fn handle(&self) -> &ServerHandle {
+        &self.handle
+    }
+
+    fn cookie_header(&self) -> Option<String> {
+        let jar = self.cookies.lock();
+        let cookies: Vec<String> = jar
+            .iter()
+            .map(|c| {
+                let (name, value) = c.name_value();
+                format!("{}={}", name, value)
+            })
+            .collect();
+
+        if cookies.is_empty() {
+            None
+        } else {
+            Some(cookies.join("; "))
+        }
+    }
+
+    fn store_cookies(&self, headers: &HeaderMap) {
+        let mut jar = self.cookies.lock();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+        for value in headers.get_all(SET_COOKIE).iter().filter_map(|v| v.to_str().ok()) {
+            let cookie = match Cookie::parse(value.to_string()) {
+                Ok(c) => c,
+                Err(e) => {
+                    warn!("Ignoring invalid Set-Cookie header `{}`: {}", value, e);
+                    continue;
+                }
+            };
+
+            let expired = cookie.max_age() == Some(0) || cookie.expires().map(|t| t.to_timespec().sec <= now).unwrap_or(false);
+            if expired {
+                jar.force_remove(cookie);
+            } else {
+                jar.add(cookie);
+            }
+        }
+    }
+}
+
+/// Builder of a request sent through a `TestClient`
+pub struct TestRequestBuilder<'c> {
+    client: &'c TestClient,
+    inner: RawRequestBuilder,
+    body: RawBody,
+    error: Option<SaphirError>,
+}
+
+impl<'c> TestRequestBuilder<'c> {
+    /// Add a header to the request
+    #[inline]
+    pub fn header<K, V>(mut self, key: K, value: V) -> Self
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<http::Error>,
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<http::Error>,
+    {
+        self.inner = self.inner.header(key, value);
+        self
+    }
+
+    /// Add a cookie to this request only, on top of the ones kept by the client
+    #[inline]
+    pub fn cookie(self, name: &str, value: &str) -> Self {
+        self.header(COOKIE, format!("{}={}", name, value))
+    }
+
+    /// Set the raw body of the request
+    #[inline]
+    pub fn body<B: Into<RawBody>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Serialize `value` as the json body of the request, using feature `json`
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize + ?Sized>(mut self, value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => self.header(http::header::CONTENT_TYPE, "application/json").body(body),
+            Err(e) => {
+                self.error = Some(e.into());
+                self
+            }
+        }
+    }
+
+    /// Serialize `value` as the urlencoded body of the request, using feature
+    /// `form`
+    #[cfg(feature = "form")]
+    pub fn form<T: serde::Serialize + ?Sized>(mut self, value: &T) -> Self {
+        match serde_urlencoded::to_string(value) {
+            Ok(body) => self.header(http::header::CONTENT_TYPE, "application/x-www-form-urlencoded").body(body),
+            Err(e) => {
+                self.error = Some(e.into());
+                self
+            }
+        }
+    }
+
+    /// Set a multipart/form-data body, using feature `multipart`
+    #[cfg(feature = "multipart")]
+    pub fn multipart(self, form: MultipartForm) -> Self {
+        let content_type = format!("multipart/form-data; boundary={}", form.boundary);
+        self.header(http::header::CONTENT_TYPE, content_type).body(form.into_bytes())
+    }
+
+    /// Dispatch the request into the server stack and collect the response
+    pub async fn send(self) -> Result<TestResponse, SaphirError> {
+        let TestRequestBuilder {
+            client,
+            mut inner,
+            body,
+            error,
+        } = self;
+
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        if let Some(cookies) = client.cookie_header() {
+            inner = inner.header(COOKIE, cookies);
+        }
+
+        let res = client.handle.inject_raw(inner.body(body)?).await?;
+        client.store_cookies(res.headers());
+
+        let (parts, body) = res.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+
+        Ok(TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        })
+    }
+}
+
+/// Multipart form-data body built for a `TestRequestBuilder`, using feature
+/// `multipart`
+#[cfg(feature = "multipart")]
+pub struct MultipartForm {
+    boundary: String,
+    body: Vec<u8>,
+}
+
+#[cfg(feature = "multipart")]
+impl Default for MultipartForm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "multipart")]
+impl MultipartForm {
+    pub fn new() -> Self {
+        static BOUNDARY_COUNT: AtomicUsize = AtomicUsize::new(0);
+        MultipartForm {
+            boundary: format!("saphir-test-boundary-{}", BOUNDARY_COUNT.fetch_add(1, Ordering::Relaxed)),
+            body: Vec::new(),
+        }
+    }
+
+    /// Add a text field
+    #[inline]
+    pub fn text(mut self, name: &str, value: &str) -> Self {
+        self.push_headers(name, None, None);
+        self.body.extend_from_slice(value.as_bytes());
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+
+    /// Add a file field
+    #[inline]
+    pub fn file<B: AsRef<[u8]>>(mut self, name: &str, file_name: &str, content_type: &str, content: B) -> Self {
+        self.push_headers(name, Some(file_name), Some(content_type));
+        self.body.extend_from_slice(content.as_ref());
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+
+    fn push_headers(&mut self, name: &str, file_name: Option<&str>, content_type: Option<&str>) {
+        self.body.extend_from_slice(format!("--{}\r\n", self.boundary).as_bytes());
+        let disposition = match file_name {
+            Some(file_name) => format!("Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n", name, file_name),
+            None => format!("Content-Disposition: form-data; name=\"{}\"\r\n", name),
+        };
+        self.body.extend_from_slice(disposition.as_bytes());
+        if let Some(content_type) = content_type {
+            self.body.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+        }
+        self.body.extend_from_slice(b"\r\n");
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.body.extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        self.body
+    }
+}
+
+/// Response collected by a `TestClient`
+#[derive(Debug)]
+pub struct TestResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl TestResponse {
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Value of the header `name`, if it is present and valid utf8
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    pub fn bytes(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// Body of the response as text, invalid utf8 sequences are replaced
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(self.body.as_ref()).into_owned()
+    }
+
+    /// Deserialize the json body of the response, using feature `json`
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, SaphirError> {
+        Ok(serde_json::from_slice(self.body.as_ref())?)
+    }
+
+    /// # Panics
+    /// Panics if the response status is not `status`
+    pub fn assert_status(&self, status: u16) -> &Self {
+        assert_eq!(self.status.as_u16(), status, "unexpected response status, body: {}", self.text());
+        self
+    }
+
+    /// # Panics
+    /// Panics if the header `name` is missing or its value is not `value`
+    pub fn assert_header(&self, name: &str, value: &str) -> &Self {
+        assert_eq!(self.header(name), Some(value), "unexpected value for header `{}`", name);
+        self
+    }
+
+    /// # Panics
+    /// Panics if the header `name` is present
+    pub fn assert_no_header(&self, name: &str) -> &Self {
+        assert!(!self.headers.contains_key(name), "unexpected header `{}`", name);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cookie::Cookie, request::Request, response::Builder as ResponseBuilder, server::Server};
+
+    async fn login(_req: Request) -> ResponseBuilder {
+        ResponseBuilder::new().status(200).cookie(Cookie::new("session", "abc"))
+    }
+
+    async fn logout(_req: Request) -> ResponseBuilder {
+        ResponseBuilder::new().status(200).cookie(Cookie::build("session", "").max_age(0).finish())
+    }
+
+    async fn whoami(req: Request) -> (u16, String) {
+        let session = req.headers().get(COOKIE).and_then(|c| c.to_str().ok()).unwrap_or_default().to_string();
+        (200, session)
+    }
+
+    async fn echo(mut req: Request) -> (u16, Vec<u8>) {
+        let body = req.body_mut().take_as::<Vec<u8>>().await.unwrap_or_default();
+        (200, body)
+    }
+
+    fn client() -> TestClient {
+        TestClient::new(Server::builder().configure_router(|r| {
+            r.route("/login", Method::POST, login)
+                .route("/logout", Method::POST, logout)
+                .route("/whoami", Method::GET, whoami)
+                .route("/echo", Method::POST, echo)
+        }))
+    }
+
+    #[tokio::test]
+    async fn cookies_persist_between_requests() {
+        let client = client();
+        client.get("/whoami").send().await.unwrap().assert_status(200);
+        assert_eq!(client.get("/whoami").send().await.unwrap().text(), "");
+
+        client.post("/login").send().await.unwrap().assert_status(200);
+        assert_eq!(client.get("/whoami").send().await.unwrap().text(), "session=abc");
+
+        client.post("/logout").send().await.unwrap();
+        assert_eq!(client.get("/whoami").send().await.unwrap().text(), "");
+    }
+
+    #[tokio::test]
+    async fn not_found_and_body() {
+        let client = client();
+        client.get("/missing").send().await.unwrap().assert_status(404);
+
+        let res = client.post("/echo").body("ping").send().await.unwrap();
+        res.assert_status(200);
+        assert_eq!(res.bytes().as_ref(), b"ping");
+    }
+
+    #[cfg(feature = "multipart")]
+    #[test]
+    fn multipart_body() {
+        let form = MultipartForm::new().text("name", "saphir").file("file", "a.txt", "text/plain", "content");
+        let boundary = form.boundary.clone();
+        let body = String::from_utf8(form.into_bytes()).unwrap();
+        assert_eq!(
+            body,
+            format!(
+                "--{b}\r\nContent-Disposition: form-data; name=\"name\"\r\n\r\nsaphir\r\n--{b}\r\nContent-Disposition: form-data; name=\"file\"; \
+                 filename=\"a.txt\"\r\nContent-Type: text/plain\r\n\r\ncontent\r\n--{b}--\r\n",
+                b = boundary
+            )
+        );
+    }
+}
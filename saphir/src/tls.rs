@@ -0,0 +1,654 @@
+//! Types describing the tls session a request was received on.
+//!
+//! When a listener is configured with
+//! [`ClientAuth`](../server/enum.ClientAuth.html), the certificate chain
+//! presented by the client is verified during the handshake and made available
+//! to guards, middlewares and handlers through
+//! [`Request::tls_info`](../request/struct.Request.html#method.tls_info):
+//!
+//! ```rust
+//! # use saphir::prelude::*;
+//! async fn client_guard(req: Request<Body>) -> Result<Request<Body>, u16> {
+//!     let cn = req
+//!         .tls_info()
+//!         .and_then(|tls| tls.client_certificate())
+//!         .and_then(|cert| cert.subject())
+//!         .and_then(|subject| subject.common_name());
+//!
+//!     match cn {
+//!         Some("trusted-client") => Ok(req),
+//!         Some(_) => Err(403),
+//!         None => Err(401),
+//!     }
+//! }
+//! ```
+
+use crate::{
+    error::SaphirError,
+    server::{ssl_loading_utils::load_certified_key, SslConfig},
+};
+use parking_lot::{Mutex, RwLock};
+use rustls::{sign::CertifiedKey, ClientHello, ResolvesServerCert};
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    sync::{Arc, Weak},
+    time::{Duration, SystemTime},
+};
+
+/// Information about the tls session of a connection
+#[derive(Debug, Clone, Default)]
+pub struct TlsInfo {
+    pub(crate) sni_hostname: Option<String>,
+    pub(crate) alpn_protocol: Option<Vec<u8>>,
+    pub(crate) peer_certificates: Vec<PeerCertificate>,
+}
+
+impl TlsInfo {
+    pub(crate) fn from_session(session: &rustls::ServerSession) -> Self {
+        use rustls::Session;
+
+        TlsInfo {
+            sni_hostname: session.get_sni_hostname().map(str::to_string),
+            alpn_protocol: session.get_alpn_protocol().map(<[u8]>::to_vec),
+            peer_certificates: session
+                .get_peer_certificates()
+                .map(|certs| certs.into_iter().map(|c| PeerCertificate::new(c.0)).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Hostname requested by the client through server name indication (SNI)
+    #[inline]
+    pub fn sni_hostname(&self) -> Option<&str> {
+        self.sni_hostname.as_deref()
+    }
+
+    /// Application protocol negotiated through ALPN, e.g. `h2`
+    #[inline]
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.alpn_protocol.as_deref()
+    }
+
+    /// Certificate chain presented by the client, leaf first. The chain has
+    /// been verified against the configured CA bundle during the handshake.
+    /// It is empty when the client did not authenticate.
+    #[inline]
+    pub fn peer_certificates(&self) -> &[PeerCertificate] {
+        &self.peer_certificates
+    }
+
+    /// Leaf certificate presented by the client, if any
+    #[inline]
+    pub fn client_certificate(&self) -> Option<&PeerCertificate> {
+        self.peer_certificates.first()
+    }
+}
+
+/// Resolve the certificate of a handshake from the server name indication
+/// sent by the client, falling back to the default certificate
+pub(crate) struct SniCertResolver {
+    default: Option<CertifiedKey>,
+    certificates: HashMap<String, CertifiedKey>,
+}
+
+impl SniCertResolver {
+    pub(crate) fn new(default: Option<CertifiedKey>) -> Self {
+        SniCertResolver {
+            default,
+            certificates: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, hostname: &str, key: CertifiedKey) {
+        self.certificates.insert(normalize_hostname(hostname), key);
+    }
+
+    fn lookup(&self, hostname: &str) -> Option<&CertifiedKey> {
+        let hostname = normalize_hostname(hostname);
+        self.certificates
+            .get(&hostname)
+            .or_else(|| {
+                let (_, parent) = hostname.split_at(hostname.find('.')?);
+                self.certificates.get(&format!("*{}", parent))
+            })
+            .or(self.default.as_ref())
+    }
+}
+
+impl ResolvesServerCert for SniCertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<CertifiedKey> {
+        match client_hello.server_name() {
+            Some(name) => self.lookup(name.into()),
+            None => self.default.as_ref(),
+        }
+        .cloned()
+    }
+}
+
+/// Certificates and keys configured on a listener
+#[derive(Clone, Default)]
+pub(crate) struct CertificateSources {
+    pub(crate) default: Option<(SslConfig, SslConfig)>,
+    pub(crate) sni: HashMap<String, (SslConfig, SslConfig)>,
+}
+
+impl CertificateSources {
+    fn load(&self) -> Result<SniCertResolver, SaphirError> {
+        let default = match &self.default {
+            Some((cert_config, key_config)) => Some(load_certified_key(cert_config, key_config)?),
+            None => None,
+        };
+
+        let mut resolver = SniCertResolver::new(default);
+        for (hostname, (cert_config, key_config)) in &self.sni {
+            resolver.add(hostname, load_certified_key(cert_config, key_config)?);
+        }
+
+        Ok(resolver)
+    }
+
+    /// Modification time of every file the certificates are loaded from
+    fn modification_times(&self) -> Vec<Option<SystemTime>> {
+        self.default
+            .iter()
+            .chain(self.sni.values())
+            .flat_map(|(cert_config, key_config)| vec![cert_config, key_config])
+            .filter_map(|config| match config {
+                SslConfig::FilePath(path) => Some(std::fs::metadata(path).and_then(|m| m.modified()).ok()),
+                SslConfig::FileData(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// Certificate resolver of a listener, whose certificates can be reloaded
+/// while it is serving
+pub(crate) struct ReloadableCertResolver {
+    sources: CertificateSources,
+    current: RwLock<SniCertResolver>,
+}
+
+impl ReloadableCertResolver {
+    pub(crate) fn load(sources: CertificateSources) -> Result<Self, SaphirError> {
+        let current = RwLock::new(sources.load()?);
+        Ok(ReloadableCertResolver { sources, current })
+    }
+
+    /// Reload the certificates from their sources, keeping the current ones
+    /// if any of them is invalid
+    pub(crate) fn reload(&self) -> Result<(), SaphirError> {
+        match self.sources.load() {
+            Ok(resolver) => {
+                *self.current.write() = resolver;
+                info!("Tls certificates reloaded");
+                Ok(())
+            }
+            Err(e) => {
+                error!("Unable to reload tls certificates, keeping the current ones: {:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Reload the certificates every time one of their files is modified,
+    /// until the resolver is dropped
+    pub(crate) async fn watch(resolver: Weak<ReloadableCertResolver>, interval: Duration) {
+        let mut last_modified = match resolver.upgrade() {
+            Some(resolver) => resolver.sources.modification_times(),
+            None => return,
+        };
+
+        loop {
+            tokio::time::delay_for(interval).await;
+            let resolver = match resolver.upgrade() {
+                Some(resolver) => resolver,
+                None => return,
+            };
+
+            let modified = resolver.sources.modification_times();
+            if modified != last_modified {
+                last_modified = modified;
+                let _ = resolver.reload();
+            }
+        }
+    }
+}
+
+impl ResolvesServerCert for ReloadableCertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<CertifiedKey> {
+        self.current.read().resolve(client_hello)
+    }
+}
+
+/// Handle reloading the certificates of the https listeners it was given to.
+///
+/// Certificates are read again from their files; new handshakes use the
+/// reloaded certificates while established connections are left untouched.
+///
+/// ```rust
+/// # use saphir::prelude::*;
+/// # use saphir::tls::TlsReloadHandle;
+/// let reload_handle = TlsReloadHandle::new();
+/// let server = Server::builder()
+///     .configure_listener(|l| {
+///         l.interface("0.0.0.0:443")
+///             .set_ssl_certificates("server.crt", "server.key")
+///             .set_tls_reload_handle(reload_handle.clone())
+///     })
+///     .build();
+///
+/// // Later, once the certificate files were rotated
+/// if let Err(e) = reload_handle.reload() {
+///     // The listeners keep serving their previous certificates
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct TlsReloadHandle {
+    resolvers: Arc<Mutex<Vec<Weak<ReloadableCertResolver>>>>,
+}
+
+impl TlsReloadHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn register(&self, resolver: &Arc<ReloadableCertResolver>) {
+        self.resolvers.lock().push(Arc::downgrade(resolver));
+    }
+
+    /// Reload the certificates of every running listener using this handle.
+    ///
+    /// If a certificate or a key cannot be loaded, the error is logged and
+    /// returned, and the listener keeps serving its current certificates.
+    pub fn reload(&self) -> Result<(), SaphirError> {
+        let mut resolvers = self.resolvers.lock();
+        resolvers.retain(|resolver| resolver.strong_count() > 0);
+
+        let mut res = Ok(());
+        for resolver in resolvers.iter().filter_map(Weak::upgrade) {
+            if let Err(e) = resolver.reload() {
+                res = Err(e);
+            }
+        }
+
+        res
+    }
+}
+
+fn normalize_hostname(hostname: &str) -> String {
+    hostname.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// A DER encoded X.509 certificate presented by the peer
+#[derive(Debug, Clone)]
+pub struct PeerCertificate {
+    der: Vec<u8>,
+    subject: Option<DistinguishedName>,
+}
+
+impl PeerCertificate {
+    pub(crate) fn new(der: Vec<u8>) -> Self {
+        let subject = der::parse_subject(&der);
+        PeerCertificate { der, subject }
+    }
+
+    /// Raw DER encoding of the certificate
+    #[inline]
+    pub fn der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// Subject of the certificate, `None` if it could not be decoded
+    #[inline]
+    pub fn subject(&self) -> Option<&DistinguishedName> {
+        self.subject.as_ref()
+    }
+}
+
+/// A X.509 distinguished name, as a list of relative distinguished names
+/// (RDN) in the order they appear in the certificate, each RDN holding one or
+/// more attribute type and value
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct DistinguishedName {
+    rdns: Vec<Vec<(String, String)>>,
+}
+
+impl DistinguishedName {
+    /// Relative distinguished names of the name, usually made of a single
+    /// attribute
+    #[inline]
+    pub fn rdns(&self) -> &[Vec<(String, String)>] {
+        &self.rdns
+    }
+
+    /// Attributes of the name, in the order they appear in the certificate.
+    /// Well known attribute types are named by their short name (`CN`, `O`,
+    /// `OU`, ...), others by their dotted OID.
+    #[inline]
+    pub fn attributes(&self) -> impl Iterator<Item = &(String, String)> {
+        self.rdns.iter().flatten()
+    }
+
+    /// Value of the first attribute of the given type
+    pub fn get(&self, attribute: &str) -> Option<&str> {
+        self.attributes().find(|(a, _)| a == attribute).map(|(_, v)| v.as_str())
+    }
+
+    /// Value of the common name (`CN`) attribute
+    #[inline]
+    pub fn common_name(&self) -> Option<&str> {
+        self.get("CN")
+    }
+}
+
+/// Format the name as described in RFC 4514, e.g. `CN=client,O=Saphir`: the
+/// RDNs are written in reverse order, the attributes of a multi-valued RDN
+/// joined by `+`
+impl Display for DistinguishedName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, rdn) in self.rdns.iter().rev().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            for (j, (attribute, value)) in rdn.iter().enumerate() {
+                if j > 0 {
+                    f.write_str("+")?;
+                }
+                write!(f, "{}=", attribute)?;
+                for (k, c) in value.chars().enumerate() {
+                    let escape = match c {
+                        ',' | '+' | '"' | '\\' | '<' | '>' | ';' => true,
+                        '#' | ' ' if k == 0 => true,
+                        ' ' if k == value.chars().count() - 1 => true,
+                        _ => false,
+                    };
+                    if escape {
+                        f.write_str("\\")?;
+                    }
+                    write!(f, "{}", c)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Minimal DER reader, only what is needed to extract the subject of a
+/// certificate
+mod der {
+    use super::DistinguishedName;
+
+    const TAG_SEQUENCE: u8 = 0x30;
+    const TAG_SET: u8 = 0x31;
+    const TAG_OID: u8 = 0x06;
+    const TAG_VERSION: u8 = 0xa0;
+    const TAG_UTF8_STRING: u8 = 0x0c;
+    const TAG_PRINTABLE_STRING: u8 = 0x13;
+    const TAG_TELETEX_STRING: u8 = 0x14;
+    const TAG_IA5_STRING: u8 = 0x16;
+    const TAG_UNIVERSAL_STRING: u8 = 0x1c;
+    const TAG_BMP_STRING: u8 = 0x1e;
+
+    const KNOWN_ATTRIBUTES: &[(&[u8], &str)] = &[
+        (&[0x55, 0x04, 0x03], "CN"),
+        (&[0x55, 0x04, 0x05], "serialNumber"),
+        (&[0x55, 0x04, 0x06], "C"),
+        (&[0x55, 0x04, 0x07], "L"),
+        (&[0x55, 0x04, 0x08], "ST"),
+        (&[0x55, 0x04, 0x09], "STREET"),
+        (&[0x55, 0x04, 0x0a], "O"),
+        (&[0x55, 0x04, 0x0b], "OU"),
+        (&[0x09, 0x92, 0x26, 0x89, 0x93, 0xf2, 0x2c, 0x64, 0x01, 0x01], "UID"),
+        (&[0x09, 0x92, 0x26, 0x89, 0x93, 0xf2, 0x2c, 0x64, 0x01, 0x19], "DC"),
+        (&[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x01], "emailAddress"),
+    ];
+
+    /// Read a single TLV, returning its tag, its content and the remaining
+    /// input
+    fn read(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+        let tag = *input.first()?;
+        let first_len = *input.get(1)?;
+        let (len, header_len) = if first_len < 0x80 {
+            (first_len as usize, 2)
+        } else {
+            let len_len = (first_len & 0x7f) as usize;
+            if len_len == 0 || len_len > 4 {
+                return None;
+            }
+            let len = input.get(2..2 + len_len)?.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize);
+            (len, 2 + len_len)
+        };
+
+        let content = input.get(header_len..header_len.checked_add(len)?)?;
+        Some((tag, content, &input[header_len + len..]))
+    }
+
+    fn read_expected(input: &[u8], expected_tag: u8) -> Option<(&[u8], &[u8])> {
+        match read(input)? {
+            (tag, content, rest) if tag == expected_tag => Some((content, rest)),
+            _ => None,
+        }
+    }
+
+    pub(super) fn parse_subject(cert: &[u8]) -> Option<DistinguishedName> {
+        let (cert, _) = read_expected(cert, TAG_SEQUENCE)?;
+        let (tbs, _) = read_expected(cert, TAG_SEQUENCE)?;
+
+        let (tag, _, mut rest) = read(tbs)?;
+        if tag == TAG_VERSION {
+            // The version is followed by the serial number
+            rest = read(rest)?.2;
+        }
+
+        // Skip signature algorithm, issuer and validity
+        for _ in 0..3 {
+            rest = read(rest)?.2;
+        }
+
+        let (subject, _) = read_expected(rest, TAG_SEQUENCE)?;
+        parse_name(subject)
+    }
+
+    pub(super) fn parse_name(mut name: &[u8]) -> Option<DistinguishedName> {
+        let mut rdns = Vec::new();
+        while !name.is_empty() {
+            let (mut rdn, rest) = read_expected(name, TAG_SET)?;
+            name = rest;
+            let mut attributes = Vec::new();
+            while !rdn.is_empty() {
+                let (atv, rest) = read_expected(rdn, TAG_SEQUENCE)?;
+                rdn = rest;
+                let (oid, value) = read_expected(atv, TAG_OID)?;
+                let (tag, value, _) = read(value)?;
+                attributes.push((attribute_name(oid), decode_string(tag, value)?));
+            }
+            rdns.push(attributes);
+        }
+
+        Some(DistinguishedName { rdns })
+    }
+
+    fn attribute_name(oid: &[u8]) -> String {
+        KNOWN_ATTRIBUTES
+            .iter()
+            .find(|(known, _)| *known == oid)
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_else(|| oid_to_string(oid))
+    }
+
+    fn oid_to_string(oid: &[u8]) -> String {
+        let mut arcs = Vec::new();
+        let mut current = 0u64;
+        for b in oid {
+            current = (current << 7) | (b & 0x7f) as u64;
+            if b & 0x80 == 0 {
+                if arcs.is_empty() {
+                    let first = if current < 80 { current / 40 } else { 2 };
+                    arcs.push(first);
+                    arcs.push(current - first * 40);
+                } else {
+                    arcs.push(current);
+                }
+                current = 0;
+            }
+        }
+
+        arcs.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(".")
+    }
+
+    fn decode_string(tag: u8, value: &[u8]) -> Option<String> {
+        match tag {
+            TAG_UTF8_STRING | TAG_PRINTABLE_STRING | TAG_IA5_STRING => String::from_utf8(value.to_vec()).ok(),
+            TAG_TELETEX_STRING => Some(value.iter().map(|b| *b as char).collect()),
+            TAG_BMP_STRING => {
+                let utf16: Vec<u16> = value.chunks(2).map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)])).collect();
+                String::from_utf16(&utf16).ok()
+            }
+            TAG_UNIVERSAL_STRING => value
+                .chunks(4)
+                .map(|c| {
+                    if c.len() == 4 {
+                        std::char::from_u32(u32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+                    } else {
+                        None
+                    }
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Self signed certificate for `CN=trusted-client, OU=Platform, O=Saphir, C=CA`
+    const CLIENT_CERT: &str = "\
+MIIB6jCCAY+gAwIBAgIUVX5Bcaje8RBSi2wovh3HmQ4H3n0wCgYIKoZIzj0EAwIw
+SjELMAkGA1UEBhMCQ0ExDzANBgNVBAoMBlNhcGhpcjERMA8GA1UECwwIUGxhdGZv
+cm0xFzAVBgNVBAMMDnRydXN0ZWQtY2xpZW50MB4XDTI2MTAxNzAwMTYwMFoXDTM2
+MTAxNDAwMTYwMFowSjELMAkGA1UEBhMCQ0ExDzANBgNVBAoMBlNhcGhpcjERMA8G
+A1UECwwIUGxhdGZvcm0xFzAVBgNVBAMMDnRydXN0ZWQtY2xpZW50MFkwEwYHKoZI
+zj0CAQYIKoZIzj0DAQcDQgAEJC07ZtcbCWmGudamO127E3r4eZaGos+lcZ52mYFK
+MpPv6N5S3UsvuGMncpgNbNDnIhWDfTxx4O89kEr8kwA6HqNTMFEwHQYDVR0OBBYE
+FDGlL88jEiAOSDRxqfaGomjOhCc2MB8GA1UdIwQYMBaAFDGlL88jEiAOSDRxqfaG
+omjOhCc2MA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDSQAwRgIhAI+4SOSG
+8QVzXZLwi6YVZamyldQomPDA7+2v+O0ZLrveAiEAsQHq/i54FAJ9Llla2mK+9uu8
+SjzA7im7jYdfKG6SqSQ=";
+
+    // PKCS8 encoded private key of `CLIENT_CERT`
+    const CLIENT_KEY: &str = "\
+MIGHAgEAMBMGByqGSM49AgEGCCqGSM49AwEHBG0wawIBAQQg9PMFEy+CZ63sWLGT
+EbX3G2coF+wwOuqyDvxdmOgBOb6hRANCAAQkLTtm1xsJaYa51qY7XbsTevh5loai
+z6VxnnaZgUoyk+/o3lLdSy+4YydymA1s0OciFYN9PHHg7z2QSvyTADoe";
+
+    fn certified_key(id: u8) -> CertifiedKey {
+        let data: String = CLIENT_KEY.split_whitespace().collect();
+        let key = rustls::sign::any_supported_type(&rustls::PrivateKey(base64::decode(&data).unwrap())).unwrap();
+        CertifiedKey::new(vec![rustls::Certificate(vec![id])], std::sync::Arc::new(key))
+    }
+
+    fn resolved_id(resolver: &SniCertResolver, hostname: &str) -> Option<u8> {
+        resolver.lookup(hostname).map(|k| k.cert[0].0[0])
+    }
+
+    #[test]
+    fn parse_name_attributes() {
+        // SEQUENCE { SET { SEQUENCE { OID 2.5.4.3, UTF8String "a,b" } }, SET { SEQUENCE
+        // { OID 1.2.3.4, PrintableString "x" } } }
+        let name = [
+            0x31, 0x0c, 0x30, 0x0a, 0x06, 0x03, 0x55, 0x04, 0x03, 0x0c, 0x03, b'a', b',', b'b', 0x31, 0x0a, 0x30, 0x08, 0x06, 0x03, 0x2a, 0x03, 0x04, 0x13,
+            0x01, b'x',
+        ];
+        let dn = der::parse_name(&name).unwrap();
+
+        assert_eq!(dn.common_name(), Some("a,b"));
+        assert_eq!(dn.get("1.2.3.4"), Some("x"));
+        assert_eq!(dn.to_string(), "1.2.3.4=x,CN=a\\,b");
+    }
+
+    #[test]
+    fn format_multi_valued_rdn() {
+        // SEQUENCE { SET { SEQUENCE { OID 2.5.4.6, PrintableString "CA" } }, SET {
+        // SEQUENCE { OID 2.5.4.11, UTF8String "a" }, SEQUENCE { OID 2.5.4.3, UTF8String
+        // "b" } } }
+        let name = [
+            0x31, 0x0b, 0x30, 0x09, 0x06, 0x03, 0x55, 0x04, 0x06, 0x13, 0x02, b'C', b'A', 0x31, 0x14, 0x30, 0x08, 0x06, 0x03, 0x55, 0x04, 0x0b, 0x0c, 0x01,
+            b'a', 0x30, 0x08, 0x06, 0x03, 0x55, 0x04, 0x03, 0x0c, 0x01, b'b',
+        ];
+        let dn = der::parse_name(&name).unwrap();
+
+        assert_eq!(dn.rdns().len(), 2);
+        assert_eq!(dn.attributes().count(), 3);
+        assert_eq!(dn.to_string(), "OU=a+CN=b,C=CA");
+    }
+
+    #[test]
+    fn parse_truncated_name() {
+        let name = [0x31, 0x0c, 0x30, 0x0a, 0x06, 0x03, 0x55];
+        assert!(der::parse_name(&name).is_none());
+    }
+
+    #[test]
+    fn parse_certificate_subject() {
+        let data: String = CLIENT_CERT.split_whitespace().collect();
+        let der = base64::decode(&data).unwrap();
+        let cert = PeerCertificate::new(der);
+        let subject = cert.subject().unwrap();
+
+        assert_eq!(subject.common_name(), Some("trusted-client"));
+        assert_eq!(subject.get("O"), Some("Saphir"));
+        assert_eq!(subject.to_string(), "CN=trusted-client,OU=Platform,O=Saphir,C=CA");
+    }
+
+    #[test]
+    fn sni_resolver_lookup() {
+        let mut resolver = SniCertResolver::new(Some(certified_key(0)));
+        resolver.add("api.example.com", certified_key(1));
+        resolver.add("*.Example.com", certified_key(2));
+
+        assert_eq!(resolved_id(&resolver, "api.example.com"), Some(1));
+        assert_eq!(resolved_id(&resolver, "API.example.com."), Some(1));
+        assert_eq!(resolved_id(&resolver, "www.example.com"), Some(2));
+        assert_eq!(resolved_id(&resolver, "a.www.example.com"), Some(0));
+        assert_eq!(resolved_id(&resolver, "example.com"), Some(0));
+
+        let resolver = SniCertResolver { default: None, ..resolver };
+        assert_eq!(resolved_id(&resolver, "example.org"), None);
+    }
+
+    #[test]
+    fn reload_keeps_certificates_on_error() {
+        let dir = std::env::temp_dir().join(format!("saphir-tls-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+        let pem = |label: &str, data: &str| format!("-----BEGIN {}-----\n{}\n-----END {}-----\n", label, data, label);
+        std::fs::write(&cert_path, pem("CERTIFICATE", CLIENT_CERT)).unwrap();
+        std::fs::write(&key_path, pem("PRIVATE KEY", CLIENT_KEY)).unwrap();
+
+        let sources = CertificateSources {
+            default: Some((
+                SslConfig::FilePath(cert_path.to_string_lossy().to_string()),
+                SslConfig::FilePath(key_path.to_string_lossy().to_string()),
+            )),
+            sni: HashMap::new(),
+        };
+        let resolver = ReloadableCertResolver::load(sources).unwrap();
+        let loaded_cert = || resolver.current.read().lookup("localhost").map(|k| k.cert[0].0.clone());
+        let original = loaded_cert().unwrap();
+
+        std::fs::write(&cert_path, "not a certificate").unwrap();
+        assert!(resolver.reload().is_err());
+        assert_eq!(loaded_cert(), Some(original.clone()));
+
+        std::fs::write(&cert_path, pem("CERTIFICATE", CLIENT_CERT)).unwrap();
+        assert!(resolver.reload().is_ok());
+        assert_eq!(loaded_cert(), Some(original));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
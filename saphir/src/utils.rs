@@ -11,7 +11,7 @@ use std::{
     collections::{HashMap, VecDeque},
     iter::FromIterator,
     str::FromStr,
-    sync::atomic::AtomicU64,
+    sync::{atomic::AtomicU64, Arc},
 };
 
 // TODO: Add possibility to match any route like /page/<path..>/view
@@ -38,13 +38,24 @@ pub enum EndpointResolverMethods {
 #[derive(Debug, Eq)]
 pub struct EndpointResolver {
     id: u64,
+    route: Arc<str>,
+    host: Option<HostMatcher>,
     path_matcher: UriPathMatcher,
     methods: EndpointResolverMethods,
 }
 
 impl Ord for EndpointResolver {
+    /// Resolvers constrained by a host come first, then the order of their
+    /// path applies
     fn cmp(&self, other: &Self) -> Ordering {
-        self.path_matcher.cmp(&other.path_matcher)
+        let host = match (&self.host, &other.host) {
+            (Some(host_self), Some(host_other)) => host_self.cmp(host_other),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+
+        host.then_with(|| self.path_matcher.cmp(&other.path_matcher))
     }
 }
 
@@ -63,9 +74,13 @@ impl PartialEq for EndpointResolver {
 impl EndpointResolver {
     pub fn new(path_str: &str, method: Method) -> Result<EndpointResolver, SaphirError> {
         let id = ENDPOINT_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let route: Arc<str> = path_str.into();
         let meta = HandlerMetadata {
             route_id: RouteId::new(id),
             name: None,
+            route: Some(route.clone()),
+            timeout_ms: None,
+            body_limit: None,
         };
         let methods = if method.is_any() {
             EndpointResolverMethods::Any(meta)
@@ -77,6 +92,8 @@ impl EndpointResolver {
 
         Ok(EndpointResolver {
             path_matcher: UriPathMatcher::new(path_str).map_err(SaphirError::Other)?,
+            host: None,
+            route,
             methods,
             id,
         })
@@ -84,8 +101,10 @@ impl EndpointResolver {
 
     pub fn new_with_metadata<I: Into<Option<HandlerMetadata>>>(path_str: &str, method: Method, meta: I) -> Result<EndpointResolver, SaphirError> {
         let id = ENDPOINT_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let route: Arc<str> = path_str.into();
         let mut meta = meta.into().unwrap_or_default();
         meta.route_id = RouteId::new(id);
+        meta.route = Some(route.clone());
         let methods = if method.is_any() {
             EndpointResolverMethods::Any(meta)
         } else {
@@ -96,11 +115,20 @@ impl EndpointResolver {
 
         Ok(EndpointResolver {
             path_matcher: UriPathMatcher::new(path_str).map_err(SaphirError::Other)?,
+            host: None,
+            route,
             methods,
             id,
         })
     }
 
+    /// Constrain the resolver to the requests whose host matches `pattern`,
+    /// e.g. `api.example.com`, `*.example.com` or `{tenant}.example.com`
+    pub fn with_host(mut self, pattern: &str) -> Result<EndpointResolver, SaphirError> {
+        self.host = Some(HostMatcher::new(pattern).map_err(SaphirError::Other)?);
+        Ok(self)
+    }
+
     pub fn add_method(&mut self, m: Method) {
         match &mut self.methods {
             EndpointResolverMethods::Specific(inner) => {
@@ -110,6 +138,9 @@ impl EndpointResolver {
                 let meta = HandlerMetadata {
                     route_id: RouteId::new(self.id),
                     name: None,
+                    route: Some(self.route.clone()),
+                    timeout_ms: None,
+                    body_limit: None,
                 };
                 inner.insert(m, meta);
             }
@@ -125,6 +156,7 @@ impl EndpointResolver {
                 }
                 let mut meta = meta.into().unwrap_or_default();
                 meta.route_id = RouteId::new(self.id);
+                meta.route = Some(self.route.clone());
                 inner.insert(m, meta);
             }
             EndpointResolverMethods::Any(_) => panic!("Adding a specific endpoint method but an Handler already defines ANY method, This is fatal"),
@@ -132,26 +164,216 @@ impl EndpointResolver {
     }
 
     pub fn resolve(&self, req: &mut Request<Body>) -> EndpointResolverResult {
+        let host = request_host(req);
+        if !self.matches_host(host.as_deref()) {
+            return EndpointResolverResult::InvalidPath;
+        }
+
         let path = req.uri().path().to_string();
         if self.path_matcher.match_all_and_capture(path, req.captures_mut()) {
-            match &self.methods {
-                EndpointResolverMethods::Specific(methods) => {
-                    if let Some(meta) = methods.get(req.method()) {
-                        EndpointResolverResult::Match(meta)
-                    } else {
-                        EndpointResolverResult::MethodNotAllowed
-                    }
-                }
-                EndpointResolverMethods::Any(meta) => EndpointResolverResult::Match(meta),
+            if let (Some(matcher), Some(host)) = (&self.host, &host) {
+                matcher.capture(host, req.captures_mut());
+            }
+            match self.metadata(req.method()) {
+                Some(meta) => EndpointResolverResult::Match(meta),
+                None => EndpointResolverResult::MethodNotAllowed,
             }
         } else {
             EndpointResolverResult::InvalidPath
         }
     }
 
+    /// Return the metadata of the handler of `method`, if any
+    pub fn metadata(&self, method: &Method) -> Option<&HandlerMetadata> {
+        match &self.methods {
+            EndpointResolverMethods::Specific(methods) => methods.get(method),
+            EndpointResolverMethods::Any(meta) => Some(meta),
+        }
+    }
+
     pub fn id(&self) -> u64 {
         self.id
     }
+
+    fn matches_host(&self, host: Option<&str>) -> bool {
+        match (&self.host, host) {
+            (None, _) => true,
+            (Some(matcher), Some(host)) => matcher.matches(host),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// Host requested by `req` in lowercase and without its port, see
+/// `Request::client_host`
+fn request_host<T>(req: &Request<T>) -> Option<String> {
+    let host = req.client_host()?;
+    let host = match host.find(']') {
+        Some(end) if host.starts_with('[') => &host[..=end],
+        _ => host.split(':').next().unwrap_or(host),
+    };
+
+    Some(host.trim_end_matches('.').to_ascii_lowercase())
+}
+
+/// Prefix tree of the endpoint resolvers, keyed on their path segments.
+///
+/// Static segments are looked up by value, the other segment matchers are
+/// only tried when a request path reaches their node. Among the resolvers
+/// matching a path, the one first in the `UriPathMatcher` order wins, as if
+/// every resolver was tried in that order.
+pub(crate) struct RouteTree {
+    /// Resolvers sorted by precedence, indexed by the nodes
+    resolvers: Vec<EndpointResolver>,
+    root: RouteNode,
+}
+
+#[derive(Default)]
+struct RouteNode {
+    statics: HashMap<String, RouteNode>,
+    /// Variable, regex and segment wildcard matchers
+    dynamics: Vec<(UriPathSegmentMatcher, RouteNode)>,
+    /// Simple resolvers whose path ends at this node
+    endpoints: Vec<usize>,
+    /// Wildcard resolvers whose path before the wildcard ends at this node
+    wildcards: Vec<usize>,
+}
+
+impl RouteTree {
+    pub fn new(mut resolvers: Vec<EndpointResolver>) -> Self {
+        resolvers.sort_unstable();
+
+        let mut root = RouteNode::default();
+        for (i, resolver) in resolvers.iter().enumerate() {
+            match &resolver.path_matcher {
+                UriPathMatcher::Simple { inner } => root.insert(inner).endpoints.push(i),
+                UriPathMatcher::Wildcard { start, .. } => root.insert(start).wildcards.push(i),
+            }
+        }
+
+        RouteTree { resolvers, root }
+    }
+
+    /// Resolve the endpoint of `req`, capturing its host and path variables
+    pub fn resolve(&self, req: &mut Request<Body>) -> EndpointResolverResult<'_> {
+        let path = req.uri().path().to_string();
+        let host = request_host(req);
+        match self.find(&path, host.as_deref(), req.method()) {
+            Ok(resolver) => {
+                if let (Some(matcher), Some(host)) = (&resolver.host, &host) {
+                    matcher.capture(host, req.captures_mut());
+                }
+                resolver.path_matcher.match_all_and_capture(path, req.captures_mut());
+                resolver
+                    .metadata(req.method())
+                    .map(EndpointResolverResult::Match)
+                    .unwrap_or(EndpointResolverResult::MethodNotAllowed)
+            }
+            Err(res) => res,
+        }
+    }
+
+    fn find(&self, path: &str, host: Option<&str>, method: &Method) -> Result<&EndpointResolver, EndpointResolverResult<'_>> {
+        let mut segments = path.split('/').collect::<Vec<_>>();
+        segments.remove(0);
+        if segments.last().map(|s| s.is_empty()).unwrap_or(false) {
+            segments.pop();
+        }
+
+        let mut lookup = Lookup {
+            resolvers: &self.resolvers,
+            host,
+            method,
+            segments: &segments,
+            best: None,
+            path_matched: false,
+        };
+        lookup.visit(&self.root, 0);
+
+        match (lookup.best, lookup.path_matched) {
+            (Some(i), _) => Ok(&self.resolvers[i]),
+            (None, true) => Err(EndpointResolverResult::MethodNotAllowed),
+            (None, false) => Err(EndpointResolverResult::InvalidPath),
+        }
+    }
+}
+
+impl RouteNode {
+    fn insert(&mut self, segments: &[UriPathSegmentMatcher]) -> &mut RouteNode {
+        let (segment, rest) = match segments.split_first() {
+            Some(split) => split,
+            None => return self,
+        };
+
+        let child = match segment {
+            UriPathSegmentMatcher::Static { segment } => self.statics.entry(segment.clone()).or_default(),
+            _ => {
+                let i = match self.dynamics.iter().position(|(m, _)| m.same_pattern(segment)) {
+                    Some(i) => i,
+                    None => {
+                        self.dynamics.push((segment.clone_pattern(), RouteNode::default()));
+                        self.dynamics.len() - 1
+                    }
+                };
+                &mut self.dynamics[i].1
+            }
+        };
+
+        child.insert(rest)
+    }
+}
+
+/// Depth first walk of the tree, keeping the matching resolver with the
+/// highest precedence
+struct Lookup<'a> {
+    resolvers: &'a [EndpointResolver],
+    host: Option<&'a str>,
+    method: &'a Method,
+    segments: &'a [&'a str],
+    best: Option<usize>,
+    path_matched: bool,
+}
+
+impl<'a> Lookup<'a> {
+    fn visit(&mut self, node: &RouteNode, depth: usize) {
+        let remaining = &self.segments[depth..];
+        for &i in &node.wildcards {
+            if let UriPathMatcher::Wildcard { end, .. } = &self.resolvers[i].path_matcher {
+                if end.len() <= remaining.len() && end.iter().rev().zip(remaining.iter().rev()).all(|(m, s)| m.matches(s)) {
+                    self.candidate(i);
+                }
+            }
+        }
+
+        match remaining.first() {
+            None => {
+                for &i in &node.endpoints {
+                    self.candidate(i);
+                }
+            }
+            Some(segment) => {
+                if let Some(child) = node.statics.get(*segment) {
+                    self.visit(child, depth + 1);
+                }
+                for (matcher, child) in &node.dynamics {
+                    if matcher.matches(segment) {
+                        self.visit(child, depth + 1);
+                    }
+                }
+            }
+        }
+    }
+
+    fn candidate(&mut self, i: usize) {
+        if !self.resolvers[i].matches_host(self.host) {
+            return;
+        }
+
+        self.path_matched = true;
+        if self.best.map(|best| i < best).unwrap_or(true) && self.resolvers[i].metadata(self.method).is_some() {
+            self.best = Some(i);
+        }
+    }
 }
 
 #[derive(Debug, Eq)]
@@ -515,6 +737,34 @@ impl UriPathSegmentMatcher {
         }
     }
 
+    /// Return true if both matchers match the same segments, regardless of
+    /// the name of their capture
+    fn same_pattern(&self, other: &UriPathSegmentMatcher) -> bool {
+        match (self, other) {
+            (UriPathSegmentMatcher::Static { segment: a }, UriPathSegmentMatcher::Static { segment: b }) => a == b,
+            (UriPathSegmentMatcher::Variable { .. }, UriPathSegmentMatcher::Variable { .. }) => true,
+            (UriPathSegmentMatcher::Custom { segment: a, .. }, UriPathSegmentMatcher::Custom { segment: b, .. }) => a.as_str() == b.as_str(),
+            (UriPathSegmentMatcher::Wildcard { prefix: pa, suffix: sa }, UriPathSegmentMatcher::Wildcard { prefix: pb, suffix: sb }) => pa == pb && sa == sb,
+            _ => false,
+        }
+    }
+
+    /// Copy of the matcher without its capture name
+    fn clone_pattern(&self) -> UriPathSegmentMatcher {
+        match self {
+            UriPathSegmentMatcher::Static { segment } => UriPathSegmentMatcher::Static { segment: segment.clone() },
+            UriPathSegmentMatcher::Variable { .. } => UriPathSegmentMatcher::Variable { name: None },
+            UriPathSegmentMatcher::Custom { segment, .. } => UriPathSegmentMatcher::Custom {
+                name: None,
+                segment: segment.clone(),
+            },
+            UriPathSegmentMatcher::Wildcard { prefix, suffix } => UriPathSegmentMatcher::Wildcard {
+                prefix: prefix.clone(),
+                suffix: suffix.clone(),
+            },
+        }
+    }
+
     #[inline]
     fn ord_index(&self) -> u16 {
         match self {
@@ -526,6 +776,76 @@ impl UriPathSegmentMatcher {
     }
 }
 
+/// Matcher of the host of a request, label per label: exact
+/// (`api.example.com`), wildcard (`*.example.com`) or capturing a label
+/// (`{tenant}.example.com`)
+#[derive(Debug)]
+pub(crate) struct HostMatcher {
+    labels: Vec<UriPathSegmentMatcher>,
+}
+
+impl Eq for HostMatcher {}
+
+impl Ord for HostMatcher {
+    /// Labels are compared from the top level domain, static labels first,
+    /// then the longest host first
+    fn cmp(&self, other: &Self) -> Ordering {
+        for (label_self, label_other) in self.labels.iter().rev().zip(other.labels.iter().rev()) {
+            let cmp = label_self.cmp(label_other);
+            if cmp != Ordering::Equal {
+                return cmp;
+            }
+        }
+
+        other.labels.len().cmp(&self.labels.len())
+    }
+}
+
+impl PartialOrd for HostMatcher {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for HostMatcher {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl HostMatcher {
+    pub fn new(pattern: &str) -> Result<HostMatcher, String> {
+        if pattern.is_empty() {
+            return Err("A host pattern should not be empty".to_string());
+        }
+
+        let labels = pattern
+            .split('.')
+            .map(|label| match UriPathSegmentMatcher::new(label)? {
+                UriPathSegmentMatcher::Static { segment } => Ok(UriPathSegmentMatcher::Static {
+                    segment: segment.to_ascii_lowercase(),
+                }),
+                matcher => Ok(matcher),
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(HostMatcher { labels })
+    }
+
+    /// Return true if `host`, in lowercase and without its port, matches
+    pub fn matches(&self, host: &str) -> bool {
+        host.split('.').count() == self.labels.len() && self.labels.iter().zip(host.split('.')).all(|(m, label)| m.matches(label))
+    }
+
+    pub fn capture(&self, host: &str, captures: &mut HashMap<String, String>) {
+        for (matcher, label) in self.labels.iter().zip(host.split('.')) {
+            if let Some(name) = matcher.name() {
+                captures.insert(name.to_string(), label.to_string());
+            }
+        }
+    }
+}
+
 pub trait MethodExtension {
     fn any() -> Self;
     fn is_any(&self) -> bool;
@@ -558,7 +878,7 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::{EndpointResolver, Method};
+    use super::{EndpointResolver, EndpointResolverResult, Method, RouteTree};
     use std::{collections::HashMap, str::FromStr};
 
     #[test]
@@ -600,4 +920,96 @@ mod tests {
         assert_eq!(&resolvers_vec[8].id(), ids.get(&"/api/v1/users/<user_id>").unwrap());
         assert_eq!(&resolvers_vec[9].id(), ids.get(&"/api/v1/users").unwrap());
     }
+
+    #[test]
+    fn test_route_tree_matches_linear_resolution() {
+        let routes = vec![
+            ("/", Method::GET),
+            ("/api/v1/users", Method::GET),
+            ("/api/v1/users", Method::POST),
+            ("/api/v1/users/keys", Method::GET),
+            ("/api/v1/users/keys/<id>", Method::GET),
+            ("/api/v1/users/keys/first", Method::GET),
+            ("/api/v1/users/keys/**", Method::GET),
+            ("/api/v1/users/keys/**/delete", Method::DELETE),
+            ("/api/v1/users/**/delete", Method::DELETE),
+            ("/api/v1/users/<user_id>/keys", Method::GET),
+            ("/api/v1/users/<user_id>/<key_id>", Method::PUT),
+            ("/api/v1/users/<user_id>", Method::GET),
+            ("/api/v1/users/<id#r([0-9]+)>/avatar", Method::GET),
+            ("/api/v1/users/<user_id>/avatar", Method::PUT),
+            ("/files/*.png", Method::GET),
+            ("/files/**", Method::GET),
+            ("/any/<path>", Method::from_str("ANY").unwrap()),
+        ];
+        let mut resolvers: Vec<EndpointResolver> = Vec::new();
+        for (path, method) in &routes {
+            match resolvers.iter_mut().find(|r| &*r.route == *path) {
+                Some(resolver) => resolver.add_method(method.clone()),
+                None => resolvers.push(EndpointResolver::new(path, method.clone()).unwrap()),
+            }
+        }
+        let tree = RouteTree::new(resolvers);
+
+        let requests = vec![
+            "/",
+            "/api/v1/users",
+            "/api/v1/users/",
+            "/api/v1/users/keys",
+            "/api/v1/users/keys/first",
+            "/api/v1/users/keys/second",
+            "/api/v1/users/keys/a/b/c",
+            "/api/v1/users/keys/a/delete",
+            "/api/v1/users/42/delete",
+            "/api/v1/users/42/keys",
+            "/api/v1/users/42/avatar",
+            "/api/v1/users/bob/avatar",
+            "/api/v1/users/42",
+            "/api/v1/users/42/43/44",
+            "/files/logo.png",
+            "/files/logo.jpg",
+            "/files/img/logo.png",
+            "/any/thing",
+            "/missing",
+        ];
+        let methods = vec![Method::GET, Method::POST, Method::PUT, Method::DELETE];
+
+        let route = |path: &str, method: Method| tree.find(path, None, &method).ok().map(|r| r.route.to_string());
+        assert_eq!(
+            route("/api/v1/users/42/avatar", Method::GET).as_deref(),
+            Some("/api/v1/users/<id#r([0-9]+)>/avatar")
+        );
+        assert_eq!(
+            route("/api/v1/users/bob/avatar", Method::PUT).as_deref(),
+            Some("/api/v1/users/<user_id>/avatar")
+        );
+        assert!(matches!(
+            tree.find("/api/v1/users/bob/avatar", None, &Method::GET),
+            Err(EndpointResolverResult::MethodNotAllowed)
+        ));
+        assert_eq!(
+            route("/api/v1/users/keys/a/delete", Method::DELETE).as_deref(),
+            Some("/api/v1/users/keys/**/delete")
+        );
+
+        for path in &requests {
+            for method in &methods {
+                let linear = tree
+                    .resolvers
+                    .iter()
+                    .find(|r| r.path_matcher.match_all_and_capture(path.to_string(), &mut HashMap::new()) && r.metadata(method).is_some())
+                    .map(|r| r.id());
+                let path_matched = tree
+                    .resolvers
+                    .iter()
+                    .any(|r| r.path_matcher.match_all_and_capture(path.to_string(), &mut HashMap::new()));
+
+                match tree.find(path, None, method) {
+                    Ok(resolver) => assert_eq!(Some(resolver.id()), linear, "{} {}", method, path),
+                    Err(EndpointResolverResult::MethodNotAllowed) => assert!(linear.is_none() && path_matched, "{} {}", method, path),
+                    Err(_) => assert!(!path_matched, "{} {}", method, path),
+                }
+            }
+        }
+    }
 }
@@ -10,6 +10,8 @@ pub struct ControllerAttr {
     pub name: String,
     pub version: Option<u16>,
     pub prefix: Option<String>,
+    pub timeout: Option<u64>,
+    pub body_limit: Option<usize>,
 }
 
 impl ControllerAttr {
@@ -17,6 +19,8 @@ impl ControllerAttr {
         let mut name = None;
         let mut version = None;
         let mut prefix = None;
+        let mut timeout = None;
+        let mut body_limit = None;
 
         let ident = crate::utils::parse_item_impl_ident(input)?;
 
@@ -48,6 +52,15 @@ impl ControllerAttr {
                     (Some("prefix"), Lit::Str(p)) => {
                         prefix = Some(p.value().trim_matches('/').to_string());
                     }
+                    (Some("timeout"), Lit::Int(t)) => {
+                        timeout = Some(
+                            t.base10_parse::<u64>()
+                                .map_err(|_| Error::new_spanned(t, "Invalid timeout, expected a number of milliseconds"))?,
+                        );
+                    }
+                    (Some("body_limit"), size) => {
+                        body_limit = Some(crate::utils::parse_byte_size(&size)?);
+                    }
                     _ => {
                         return Err(Error::new_spanned(path, "Unexpected Param in controller macro"));
                     }
@@ -57,7 +70,14 @@ impl ControllerAttr {
 
         let name = name.unwrap_or_else(|| ident.to_string().to_lowercase().trim_end_matches("controller").to_string());
 
-        Ok(ControllerAttr { ident, name, version, prefix })
+        Ok(ControllerAttr {
+            ident,
+            name,
+            version,
+            prefix,
+            timeout,
+            body_limit,
+        })
     }
 }
 
@@ -105,8 +125,15 @@ fn gen_controller_handlers_fn(attr: &ControllerAttr, handlers: &[HandlerRepr]) -
     let ctrl_ident = attr.ident.clone();
 
     for handler in handlers {
-        let HandlerAttrs { methods_paths, guards, .. } = &handler.attrs;
+        let HandlerAttrs {
+            methods_paths,
+            guards,
+            timeout,
+            body_limit,
+            ..
+        } = &handler.attrs;
         let handler_ident = handler.original_method.sig.ident.clone();
+        let options = gen_route_options(timeout.or(attr.timeout), body_limit.or(attr.body_limit));
 
         for (method, path) in methods_paths {
             let method = method.as_str();
@@ -127,12 +154,19 @@ fn gen_controller_handlers_fn(attr: &ControllerAttr, handlers: &[HandlerRepr]) -
                 }
 
                 (quote! {
-                    .add_with_guards_and_name(#handler_name, Method::from_str(#method).expect("Method was validated the macro expansion"), #path, #ctrl_ident::#handler_ident, |g| {
+                    .add_with_guards_and_name(#handler_name, Method::from_str(#method).expect("Method was validated by the macro expansion"), #path, #ctrl_ident::#handler_ident, |g| {
                         g #guard_stream
                     })
                 })
                 .to_tokens(&mut handler_stream);
             }
+
+            if let Some(options) = &options {
+                (quote! {
+                    .options(#options)
+                })
+                .to_tokens(&mut handler_stream);
+            }
         }
     }
 
@@ -145,3 +179,19 @@ fn gen_controller_handlers_fn(attr: &ControllerAttr, handlers: &[HandlerRepr]) -
     };
     quoted_h
 }
+
+fn gen_route_options(timeout: Option<u64>, body_limit: Option<usize>) -> Option<TokenStream> {
+    if timeout.is_none() && body_limit.is_none() {
+        return None;
+    }
+
+    let mut options = quote! { RouteOptions::new() };
+    if let Some(timeout) = timeout {
+        (quote! { .timeout(#timeout) }).to_tokens(&mut options);
+    }
+    if let Some(body_limit) = body_limit {
+        (quote! { .body_limit(#body_limit) }).to_tokens(&mut options);
+    }
+
+    Some(options)
+}
@@ -309,6 +309,8 @@ pub struct HandlerAttrs {
     pub methods_paths: Vec<(Method, String)>,
     pub guards: Vec<GuardDef>,
     pub cookie: bool,
+    pub timeout: Option<u64>,
+    pub body_limit: Option<usize>,
 }
 
 #[derive(Clone)]
@@ -342,6 +344,8 @@ impl HandlerAttrs {
         let mut methods_paths = Vec::new();
         let mut guards = Vec::new();
         let mut cookie = false;
+        let mut timeout = None;
+        let mut body_limit = None;
 
         let metas = attrs.iter_mut().map(|attr| attr.parse_meta()).collect::<Result<Vec<Meta>>>()?;
         for meta in metas {
@@ -404,6 +408,29 @@ impl HandlerAttrs {
                             };
 
                             guards.push(guard);
+                        } else if ident.to_string().eq("timeout") {
+                            if timeout.is_some() {
+                                return Err(Error::new_spanned(attribute, "Cannot specify the timeout twice"));
+                            }
+
+                            if let (Some(NestedMeta::Lit(Lit::Int(ms))), 1) = (attribute.nested.first(), attribute.nested.len()) {
+                                timeout = Some(
+                                    ms.base10_parse::<u64>()
+                                        .map_err(|_| Error::new_spanned(ms, "Invalid timeout, expected a number of milliseconds"))?,
+                                );
+                            } else {
+                                return Err(Error::new_spanned(attribute, "Expected a timeout in milliseconds, like `#[timeout(5000)]`"));
+                            }
+                        } else if ident.to_string().eq("body_limit") {
+                            if body_limit.is_some() {
+                                return Err(Error::new_spanned(attribute, "Cannot specify the body limit twice"));
+                            }
+
+                            if let (Some(NestedMeta::Lit(size)), 1) = (attribute.nested.first(), attribute.nested.len()) {
+                                body_limit = Some(crate::utils::parse_byte_size(size)?);
+                            } else {
+                                return Err(Error::new_spanned(attribute, "Expected a size in bytes, like `#[body_limit(10MB)]`"));
+                            }
                         } else if ident.to_string().eq("openapi") {
                             if attribute.nested.is_empty() {
                                 return Err(Error::new_spanned(ident, "openapi attribute cannot be empty"));
@@ -634,7 +661,13 @@ impl HandlerAttrs {
             ));
         }
 
-        Ok(HandlerAttrs { methods_paths, guards, cookie })
+        Ok(HandlerAttrs {
+            methods_paths,
+            guards,
+            cookie,
+            timeout,
+            body_limit,
+        })
     }
 }
 
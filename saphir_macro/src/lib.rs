@@ -38,6 +38,11 @@ mod utils;
 /// - `version=<u16>` use for api version, the version will be added before the
 ///   name as the controller basepath
 /// - `prefix="<prefix>"` add a prefix before the basepath and the version.
+/// - `timeout=<ms>` set the request timeout of every handler of the
+///   controller, a handler can override it with `#[timeout(<ms>)]`
+/// - `body_limit=<size>` set the maximum request body size of every handler
+///   of the controller, e.g. `body_limit=1MB`, a handler can override it with
+///   `#[body_limit(<size>)]`
 ///
 /// ##Example
 ///
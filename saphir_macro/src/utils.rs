@@ -1,5 +1,5 @@
 use proc_macro2::Ident;
-use syn::{Error, ItemImpl, Result, Type};
+use syn::{Error, ItemImpl, Lit, Result, Type};
 
 pub fn parse_item_impl_ident(input: &ItemImpl) -> Result<Ident> {
     if let Type::Path(p) = input.self_ty.as_ref() {
@@ -10,3 +10,32 @@ pub fn parse_item_impl_ident(input: &ItemImpl) -> Result<Ident> {
 
     Err(Error::new_spanned(input, "Unable to parse impl ident. this is fatal"))
 }
+
+/// Parse a size in bytes, with an optional `B`, `KB`, `MB` or `GB` unit in
+/// multiples of 1024, e.g. `10MB` or `"512KB"`
+pub fn parse_byte_size(lit: &Lit) -> Result<usize> {
+    let (digits, unit) = match lit {
+        Lit::Int(i) => (i.base10_digits().to_string(), i.suffix().to_string()),
+        Lit::Str(s) => {
+            let value = s.value();
+            let value = value.trim();
+            let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+            (value[..split].to_string(), value[split..].trim().to_string())
+        }
+        _ => return Err(Error::new_spanned(lit, "Expected a size in bytes, like `10MB`")),
+    };
+
+    let multiplier: usize = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" => 1024,
+        "MB" => 1024 * 1024,
+        "GB" => 1024 * 1024 * 1024,
+        _ => return Err(Error::new_spanned(lit, "Invalid size unit, expected one of B, KB, MB or GB")),
+    };
+
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| Error::new_spanned(lit, "Invalid size in bytes"))
+}